@@ -0,0 +1,409 @@
+use crate::shell::Shell;
+use crate::variables::is_valid_name;
+
+// Integer arithmetic for $((...)), ((...)) and let. The operators and their precedence are the ones of C, with ** for
+// the power. The variables are read from the shell, an unset or empty variable counts as 0 and a variable holding an
+// expression is evaluated. The assignment operators and ++/-- store the results into the variables
+
+// The longest operators must come before their prefixes
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "&=",
+    "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~", "?", ":", "=", "(", ")", ","
+];
+
+const ASSIGNMENT_OPERATORS: [&str; 11] = ["=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|="];
+
+// The binary operators from the lowest precedence to the highest, ** and the unary operators bind tighter than all of them
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]
+];
+
+// A variable holding an expression that refers to a variable holding an expression... is evaluated up to this depth
+const MAX_RECURSION_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Operator(&'static str)
+}
+
+// A value and the variable it was read from, if any. Only values read from a variable can be assigned to
+struct Operand {
+    value: i64,
+    variable: Option<String>
+}
+
+impl Operand {
+    fn value(value: i64) -> Self {
+        Operand { value, variable: None }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            // a number can have a base and digits up to 64, e.g 16#ff or 64#_@
+            let start = pos;
+            while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || matches!(chars[pos], '_' | '#' | '@')) {
+                pos += 1;
+            }
+            let text: String = chars[start..pos].iter().collect();
+            tokens.push(if c.is_ascii_digit() { Token::Number(text) } else { Token::Name(text) });
+        } else {
+            let operator = OPERATORS.iter()
+                .find(|op| op.chars().enumerate().all(|(i, o)| chars.get(pos + i) == Some(&o)))
+                .ok_or_else(|| format!("syntax error: invalid arithmetic operator (error token is \"{}\")", chars[pos..].iter().collect::<String>()))?;
+            pos += operator.len();
+            tokens.push(Token::Operator(operator));
+        }
+    }
+    Ok(tokens)
+}
+
+// Parse an integer constant: decimal, octal with a leading 0, hexadecimal with 0x or base#digits with a base from 2 to 64
+fn parse_number(text: &str) -> Result<i64, String> {
+    let invalid = || format!("value too great for base (error token is \"{}\")", text);
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        let base = base.parse::<u32>().ok().filter(|b| (2..=64).contains(b)).ok_or_else(|| format!("invalid arithmetic base (error token is \"{}\")", text))?;
+        (base, digits)
+    } else if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // up to base 36 the letters are case insensitive, above it a-z are 10-35, A-Z 36-61, @ 62 and _ 63
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid())
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+struct Evaluator<'a> {
+    shell: &'a mut Shell,
+    tokens: Vec<Token>,
+    pos: usize,
+    // cleared in the parts that are skipped, e.g the right side of '0 && x++'. They are parsed but have no effects
+    evaluating: bool,
+    depth: usize,
+    // set when the error came from the value of a variable, it already names the expression it is in
+    inner_error: bool
+}
+
+impl<'a> Evaluator<'a> {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None
+        }
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), String> {
+        if self.peek_operator() != Some(operator) {
+            return Err(self.syntax_error());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn syntax_error(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some(Token::Number(text)) | Some(Token::Name(text)) => format!("syntax error in expression (error token is \"{}\")", text),
+            Some(Token::Operator(op)) => format!("syntax error in expression (error token is \"{}\")", op),
+            None => String::from("syntax error: operand expected")
+        }
+    }
+
+    fn variable_value(&mut self, name: &str) -> Result<i64, String> {
+        let text = self.shell.variables.get(name).unwrap_or("").trim().to_string();
+        if text.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(value) = parse_number(&text) {
+            return Ok(value);
+        }
+        if self.depth >= MAX_RECURSION_DEPTH {
+            self.inner_error = true;
+            return Err(format!("{}: expression recursion level exceeded", name));
+        }
+        let result = evaluate_at_depth(self.shell, &text, self.depth + 1);
+        self.inner_error = result.is_err();
+        result
+    }
+
+    fn store(&mut self, variable: &Option<String>, value: i64) -> Result<(), String> {
+        if !self.evaluating {
+            return Ok(());
+        }
+        match variable {
+            Some(name) => self.shell.variables.set(name, &value.to_string()),
+            None => Err(String::from("attempted assignment to non-variable"))
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<Operand, String> {
+        let mut operand = self.parse_assignment()?;
+        while self.peek_operator() == Some(",") {
+            self.pos += 1;
+            operand = self.parse_assignment()?;
+        }
+        Ok(operand)
+    }
+
+    fn parse_assignment(&mut self) -> Result<Operand, String> {
+        let target = self.parse_ternary()?;
+        let Some(operator) = self.peek_operator().filter(|op| ASSIGNMENT_OPERATORS.contains(op)) else {
+            return Ok(target);
+        };
+        if target.variable.is_none() {
+            return Err(String::from("attempted assignment to non-variable"));
+        }
+        self.pos += 1;
+        let right = self.parse_assignment()?.value;
+        let value = match operator {
+            "=" => right,
+            _ => self.apply(&operator[..operator.len() - 1], target.value, right)?
+        };
+        self.store(&target.variable, value)?;
+        Ok(Operand::value(value))
+    }
+
+    fn parse_ternary(&mut self) -> Result<Operand, String> {
+        let condition = self.parse_binary(0)?;
+        if self.peek_operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let evaluating = self.evaluating;
+        self.evaluating = evaluating && condition.value != 0;
+        let if_true = self.parse_comma()?.value;
+        self.expect(":")?;
+        self.evaluating = evaluating && condition.value == 0;
+        let if_false = self.parse_ternary()?.value;
+        self.evaluating = evaluating;
+        Ok(Operand::value(if condition.value != 0 { if_true } else { if_false }))
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Operand, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.parse_power();
+        }
+        let mut left = self.parse_binary(level + 1)?;
+        while let Some(operator) = self.peek_operator().filter(|op| BINARY_OPERATORS[level].contains(op)) {
+            self.pos += 1;
+            // the right side of && and || is evaluated only if it decides the result
+            let evaluating = self.evaluating;
+            match operator {
+                "&&" => self.evaluating = evaluating && left.value != 0,
+                "||" => self.evaluating = evaluating && left.value == 0,
+                _ => ()
+            }
+            let right = self.parse_binary(level + 1)?;
+            self.evaluating = evaluating;
+            left = Operand::value(self.apply(operator, left.value, right.value)?);
+        }
+        Ok(left)
+    }
+
+    fn parse_power(&mut self) -> Result<Operand, String> {
+        let base = self.parse_unary()?;
+        if self.peek_operator() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let exponent = self.parse_power()?;
+        Ok(Operand::value(self.apply("**", base.value, exponent.value)?))
+    }
+
+    fn parse_unary(&mut self) -> Result<Operand, String> {
+        let Some(operator) = self.peek_operator().filter(|op| ["+", "-", "!", "~", "++", "--"].contains(op)) else {
+            return self.parse_postfix();
+        };
+        self.pos += 1;
+        let operand = self.parse_unary()?;
+        let value = match operator {
+            "+" => operand.value,
+            "-" => operand.value.wrapping_neg(),
+            "!" => (operand.value == 0) as i64,
+            "~" => !operand.value,
+            _ => {
+                let value = if operator == "++" { operand.value.wrapping_add(1) } else { operand.value.wrapping_sub(1) };
+                self.store(&operand.variable, value)?;
+                value
+            }
+        };
+        Ok(Operand::value(value))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Operand, String> {
+        let operand = self.parse_primary()?;
+        match self.peek_operator() {
+            Some(operator @ ("++" | "--")) if operand.variable.is_some() => {
+                self.pos += 1;
+                let value = if operator == "++" { operand.value.wrapping_add(1) } else { operand.value.wrapping_sub(1) };
+                self.store(&operand.variable, value)?;
+                Ok(Operand::value(operand.value))
+            },
+            _ => Ok(operand)
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Operand, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Number(text)) => {
+                self.pos += 1;
+                Ok(Operand::value(parse_number(&text)?))
+            },
+            Some(Token::Name(name)) if is_valid_name(&name) => {
+                self.pos += 1;
+                let value = if self.evaluating { self.variable_value(&name)? } else { 0 };
+                Ok(Operand { value, variable: Some(name) })
+            },
+            Some(Token::Operator("(")) => {
+                self.pos += 1;
+                let operand = self.parse_comma()?;
+                self.expect(")")?;
+                Ok(Operand::value(operand.value))
+            },
+            _ => Err(self.syntax_error())
+        }
+    }
+
+    fn apply(&self, operator: &str, left: i64, right: i64) -> Result<i64, String> {
+        if matches!(operator, "/" | "%") && right == 0 {
+            // a skipped division doesn't fail
+            return if self.evaluating { Err(String::from("division by 0")) } else { Ok(0) };
+        }
+        Ok(match operator {
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "**" if right < 0 => return Err(String::from("exponent less than 0")),
+            "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "<" => (left < right) as i64,
+            ">" => (left > right) as i64,
+            "<=" => (left <= right) as i64,
+            ">=" => (left >= right) as i64,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "&" => left & right,
+            "|" => left | right,
+            "^" => left ^ right,
+            "&&" => (left != 0 && right != 0) as i64,
+            _ => (left != 0 || right != 0) as i64
+        })
+    }
+}
+
+fn evaluate_at_depth(shell: &mut Shell, expression: &str, depth: usize) -> Result<i64, String> {
+    let tokens = tokenize(expression).map_err(|e| format!("{}: {}", expression.trim(), e))?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut evaluator = Evaluator { shell, tokens, pos: 0, evaluating: true, depth, inner_error: false };
+    let result = evaluator.parse_comma().and_then(|operand| match evaluator.pos < evaluator.tokens.len() {
+        true => Err(evaluator.syntax_error()),
+        false => Ok(operand.value)
+    });
+    if evaluator.inner_error {
+        return result;
+    }
+    result.map_err(|e| format!("{}: {}", expression.trim(), e))
+}
+
+// Evaluate the expression. The parameters and command substitutions in it must have been expanded already
+pub fn evaluate(shell: &mut Shell, expression: &str) -> Result<i64, String> {
+    evaluate_at_depth(shell, expression, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(shell: &mut Shell, expression: &str) -> Result<i64, String> {
+        evaluate(shell, expression)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let mut shell = Shell::make(None);
+        let cases = [
+            ("2 + 3 * 4", 14), ("(2 + 3) * 4", 20), ("2 ** 3 ** 2", 512), ("10 - 3 - 2", 5), ("1 << 2 + 1", 8),
+            ("5 > 3 == 1", 1), ("1 | 2 ^ 3 & 2", 1), ("!0 && 3 > 2 || 0", 1), ("1 ? 2 : 3", 2), ("0 ? 2 : 0 ? 3 : 4", 4),
+            ("-2 ** 2", 4), ("~0", -1), ("-7 / 2", -3), ("-7 % 3", -1), ("1, 2, 3", 3), ("", 0)
+        ];
+        for (expression, value) in cases {
+            assert_eq!(eval(&mut shell, expression), Ok(value), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn number_bases() {
+        let mut shell = Shell::make(None);
+        assert_eq!(eval(&mut shell, "0x10 + 010 + 2#101 + 16#ff"), Ok(16 + 8 + 5 + 255));
+        assert!(eval(&mut shell, "08").is_err());
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let mut shell = Shell::make(None);
+        assert!(eval(&mut shell, "1 / 0").is_err());
+        assert!(eval(&mut shell, "5 % (2 - 2)").is_err());
+        // the branch that isn't taken isn't evaluated
+        assert_eq!(eval(&mut shell, "0 && 1 / 0"), Ok(0));
+        assert_eq!(eval(&mut shell, "1 ? 1 : 1 / 0"), Ok(1));
+    }
+
+    #[test]
+    fn variables_and_assignments() {
+        let mut shell = Shell::make(None);
+        assert_eq!(eval(&mut shell, "arith_x = 5, arith_x += 2, arith_x * 2"), Ok(14));
+        assert_eq!(shell.variables.get("arith_x"), Some("7"));
+        assert_eq!(eval(&mut shell, "arith_x++ + ++arith_x"), Ok(7 + 9));
+        assert_eq!(eval(&mut shell, "arith_unset + 1"), Ok(1));
+        shell.variables.set("arith_y", "arith_x - 4").unwrap();
+        assert_eq!(eval(&mut shell, "arith_y * 2"), Ok(10));
+        assert!(eval(&mut shell, "3 = 4").is_err());
+        assert!(eval(&mut shell, "1 +").is_err());
+        assert!(eval(&mut shell, "(1").is_err());
+    }
+
+    #[test]
+    fn errors_name_the_expression_once() {
+        let mut shell = Shell::make(None);
+        shell.variables.set("arith_self", "arith_self").unwrap();
+        assert_eq!(eval(&mut shell, "arith_self"), Err(String::from("arith_self: expression recursion level exceeded")));
+        shell.variables.set("arith_a", "arith_b").unwrap();
+        shell.variables.set("arith_b", "1 +").unwrap();
+        assert_eq!(eval(&mut shell, "2 * arith_a"), Err(String::from("1 +: syntax error: operand expected")));
+        assert_eq!(eval(&mut shell, "2 *"), Err(String::from("2 *: syntax error: operand expected")));
+    }
+}
@@ -0,0 +1,170 @@
+// Brace expansion turns one word into several before any other expansion: 'a{b,c}d' gives 'abd' and 'acd',
+// '{1..5}' the numbers from 1 to 5 and '{a..e..2}' every second letter. The braces must be unquoted and, apart from a
+// sequence, have a comma at their top level, otherwise they are taken literally
+
+// Skip a quoted part, an escaped character or a ${...}, $(...) or `...` starting at pos. Returns the position after it,
+// or None if there is nothing to skip at pos
+fn skip_quoted(chars: &[char], pos: usize) -> Option<usize> {
+    let closing = |open: char, close: char, start: usize| {
+        let mut depth = 0;
+        let mut i = start;
+        while i < chars.len() {
+            if let Some(next) = skip_quoted(chars, i) {
+                i = next;
+                continue;
+            }
+            if chars[i] == open {
+                depth += 1;
+            } else if chars[i] == close {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            i += 1;
+        }
+        chars.len()
+    };
+    match chars[pos] {
+        '\\' => Some((pos + 2).min(chars.len())),
+        '\'' | '"' | '`' => {
+            let quote = chars[pos];
+            let mut i = pos + 1;
+            while i < chars.len() && chars[i] != quote {
+                i += if chars[i] == '\\' && quote != '\'' { 2 } else { 1 };
+            }
+            Some((i + 1).min(chars.len()))
+        },
+        '$' if chars.get(pos + 1) == Some(&'{') => Some(closing('{', '}', pos + 1)),
+        '$' if chars.get(pos + 1) == Some(&'(') => Some(closing('(', ')', pos + 1)),
+        _ => None
+    }
+}
+
+// Find the '}' matching the '{' at start and the top level commas between them
+fn find_closing(chars: &[char], start: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = start;
+    while i < chars.len() {
+        if let Some(next) = skip_quoted(chars, i) {
+            i = next;
+            continue;
+        }
+        match chars[i] {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((i, commas));
+                }
+            },
+            ',' if depth == 1 => commas.push(i),
+            _ => ()
+        }
+        i += 1;
+    }
+    None
+}
+
+// The words of a sequence, {x..y} or {x..y..step}, with x and y both numbers or both single characters
+fn sequence(text: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = text.split("..").collect();
+    let (start, end, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, None),
+        [start, end, step] => (*start, *end, Some(step.parse::<i64>().ok()?)),
+        _ => return None
+    };
+    let step = step.map(|s| s.unsigned_abs()).filter(|s| *s != 0).unwrap_or(1) as usize;
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // a leading zero pads all the numbers to the same width
+        let has_padding = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+        let width = if has_padding(start) || has_padding(end) { start.len().max(end.len()) } else { 0 };
+        let numbers: Vec<i64> = if first <= last {
+            (first..=last).step_by(step).collect()
+        } else {
+            (last..=first).rev().step_by(step).collect()
+        };
+        return Some(numbers.into_iter().map(|n| format!("{:0width$}", n, width = width)).collect());
+    }
+    let (mut first_chars, mut last_chars) = (start.chars(), end.chars());
+    match (first_chars.next(), first_chars.next(), last_chars.next(), last_chars.next()) {
+        (Some(first), None, Some(last), None) if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() => {
+            let letters: Vec<char> = if first <= last {
+                (first..=last).step_by(step).collect()
+            } else {
+                (last..=first).rev().step_by(step).collect()
+            };
+            Some(letters.into_iter().map(String::from).collect())
+        },
+        _ => None
+    }
+}
+
+// Expand the braces of a word written in the input (quotes included). A word without braces gives itself
+pub fn expand(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(next) = skip_quoted(&chars, i) {
+            i = next;
+            continue;
+        }
+        if chars[i] == '{' {
+            if let Some((end, commas)) = find_closing(&chars, i) {
+                let text = |from: usize, to: usize| chars[from..to].iter().collect::<String>();
+                let alternatives = if commas.is_empty() {
+                    sequence(&text(i + 1, end))
+                } else {
+                    let bounds: Vec<usize> = std::iter::once(i).chain(commas).chain(std::iter::once(end)).collect();
+                    Some(bounds.windows(2).map(|w| text(w[0] + 1, w[1])).collect())
+                };
+                if let Some(alternatives) = alternatives {
+                    let (prefix, suffix) = (text(0, i), text(end + 1, chars.len()));
+                    // the alternatives and the rest of the word can have braces of their own
+                    return alternatives.iter()
+                        .flat_map(|alternative| expand(&format!("{}{}{}", prefix, alternative, suffix)))
+                        .collect();
+                }
+            }
+        }
+        i += 1;
+    }
+    vec![String::from(word)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_all(word: &str) -> String {
+        expand(word).join(" ")
+    }
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(expand_all("file.{txt,bak}"), "file.txt file.bak");
+        assert_eq!(expand_all("a{b,c{d,e}}f"), "abf acdf acef");
+        assert_eq!(expand_all("x{,y}"), "x xy");
+        assert_eq!(expand_all("{1..3}{x,y}"), "1x 1y 2x 2y 3x 3y");
+    }
+
+    #[test]
+    fn sequences_with_steps() {
+        assert_eq!(expand_all("{1..10..3}"), "1 4 7 10");
+        assert_eq!(expand_all("{5..1..2}"), "5 3 1");
+        assert_eq!(expand_all("{-2..2..2}"), "-2 0 2");
+        assert_eq!(expand_all("{01..10..3}"), "01 04 07 10");
+        assert_eq!(expand_all("{a..e..2}"), "a c e");
+        assert_eq!(expand_all("{z..x}"), "z y x");
+    }
+
+    #[test]
+    fn words_without_a_valid_expansion_stay() {
+        assert_eq!(expand_all("{a}"), "{a}");
+        assert_eq!(expand_all("{1..b}"), "{1..b}");
+        assert_eq!(expand_all("\"{a,b}\""), "\"{a,b}\"");
+        assert_eq!(expand_all("\\{a,b\\}"), "\\{a,b\\}");
+        assert_eq!(expand_all("${x,y}"), "${x,y}");
+    }
+}
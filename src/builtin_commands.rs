@@ -1,33 +1,1256 @@
-use std::env::set_current_dir;
+use std::env::{current_dir, set_current_dir};
+use std::fs;
 use std::path::Path;
-use std::io;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
 
-pub enum BUILTINS {
-    NONE,
-    CD,
-    EXIT
+use crate::arithmetic;
+use crate::command_execution;
+use crate::conditional;
+use crate::jobs;
+use crate::parser::parse;
+use crate::printf;
+use crate::shell::{ControlFlow, FunctionFrame, Shell, ShellOptions};
+use crate::tokenizer::{tokenize, RESERVED_WORDS};
+use crate::variables::{is_valid_name, split_assignment, Variable};
+
+// A command run by the interpreter itself. It gets the argument vector (its name first) and the standard streams,
+// the redirections of the command are already applied to them. Inside a pipeline it runs in a copy of the interpreter
+pub trait Builtin: Sync {
+    fn name(&self) -> &'static str;
+
+    // Returns the exit status. An error writing to the streams ends the builtin
+    fn run(&self, shell: &mut Shell, argv: &[String], streams: &mut BuiltinIo) -> io::Result<i32>;
+}
+
+// The standard streams of a builtin
+pub struct BuiltinIo<'a> {
+    // none of the builtins reads its input yet
+    #[allow(dead_code)]
+    pub stdin: &'a mut dyn Read,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write
+}
+
+// A builtin implemented by a function taking the arguments after the name
+struct FunctionBuiltin {
+    name: &'static str,
+    function: fn(&mut Shell, &[String], &mut BuiltinIo) -> io::Result<i32>
+}
+
+impl Builtin for FunctionBuiltin {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+        (self.function)(shell, &argv[1..], streams)
+    }
+}
+
+// All the builtins. The executor looks a command name up here before it searches for a program
+static REGISTRY: [&dyn Builtin; 31] = [
+    &FunctionBuiltin { name: "cd", function: cd },
+    &FunctionBuiltin { name: "exit", function: exit },
+    &FunctionBuiltin { name: "jobs", function: jobs::jobs },
+    &FunctionBuiltin { name: "fg", function: jobs::fg },
+    &FunctionBuiltin { name: "bg", function: jobs::bg },
+    &FunctionBuiltin { name: "wait", function: jobs::wait },
+    &FunctionBuiltin { name: "export", function: export },
+    &FunctionBuiltin { name: "unset", function: unset },
+    &FunctionBuiltin { name: "readonly", function: readonly },
+    &FunctionBuiltin { name: "shopt", function: shopt },
+    &FunctionBuiltin { name: "let", function: let_expressions },
+    &FunctionBuiltin { name: "pushd", function: pushd },
+    &FunctionBuiltin { name: "popd", function: popd },
+    &FunctionBuiltin { name: "dirs", function: dirs },
+    &FunctionBuiltin { name: "echo", function: echo },
+    &FunctionBuiltin { name: "printf", function: printf },
+    &FunctionBuiltin { name: "pwd", function: pwd },
+    &FunctionBuiltin { name: "true", function: true_builtin },
+    &FunctionBuiltin { name: "false", function: false_builtin },
+    &FunctionBuiltin { name: "type", function: type_builtin },
+    &FunctionBuiltin { name: "command", function: command },
+    &FunctionBuiltin { name: "which", function: which },
+    &FunctionBuiltin { name: "test", function: test },
+    &FunctionBuiltin { name: "[", function: bracket },
+    &FunctionBuiltin { name: "break", function: break_builtin },
+    &FunctionBuiltin { name: "continue", function: continue_builtin },
+    &FunctionBuiltin { name: "return", function: return_builtin },
+    &FunctionBuiltin { name: "local", function: local },
+    &FunctionBuiltin { name: "shift", function: shift },
+    &FunctionBuiltin { name: "source", function: source },
+    &FunctionBuiltin { name: ".", function: source }
+];
+
+// The builtins whose arguments of the form NAME=value are expanded like assignments, without splitting them into fields
+pub const DECLARATION_BUILTINS: [&str; 3] = ["export", "local", "readonly"];
+
+pub fn find(name: &str) -> Option<&'static dyn Builtin> {
+    REGISTRY.iter().find(|builtin| builtin.name() == name).copied()
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    find(name).is_some()
+}
+
+// The absolute path of dir relative to base, with the '.' and '..' components removed without resolving the symbolic links
+fn logical_path(base: &str, dir: &str) -> String {
+    let joined = if dir.starts_with('/') { String::from(dir) } else { format!("{}/{}", base, dir) };
+    let mut components: Vec<&str> = Vec::new();
+    for component in joined.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => { components.pop(); },
+            _ => components.push(component)
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+// Change the working directory and update PWD and OLDPWD. Unless physical is set, the new PWD keeps the symbolic links
+// the path went through, e.g 'cd link/..' comes back to the same directory. If the logical path doesn't work,
+// the path is resolved by the system
+fn change_working_dir(shell: &mut Shell, dir: &str, physical: bool) -> io::Result<()> {
+    let old_pwd = shell.working_dir();
+    let logical = logical_path(&old_pwd, dir);
+    let new_pwd = if !physical && set_current_dir(&logical).is_ok() {
+        logical
+    } else {
+        set_current_dir(Path::new(dir))?;
+        current_dir()?.to_string_lossy().into_owned()
+    };
+    shell.dir_changed = true;
+    shell.variables.set("OLDPWD", &old_pwd).map_err(io::Error::other)?;
+    shell.variables.set("PWD", &new_pwd).map_err(io::Error::other)
+}
+
+// The message for an error of the system, without the ' (os error N)' that the standard library adds to it
+pub fn error_message(e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::NotFound => String::from("No such file or directory"),
+        io::ErrorKind::PermissionDenied => String::from("Permission denied"),
+        io::ErrorKind::NotADirectory => String::from("Not a directory"),
+        _ => {
+            let message = e.to_string();
+            match message.rfind(" (os error ") {
+                Some(end) => String::from(&message[..end]),
+                None => message
+            }
+        }
+    }
+}
+
+// The directory for cd found through CDPATH, for a relative path that doesn't start with '.' or '..'.
+// Returns the directory and whether it was found under a non-empty CDPATH entry (cd then prints the new directory)
+fn search_cdpath(shell: &Shell, dir: &str) -> Option<(String, bool)> {
+    let first_component = dir.split('/').next().unwrap_or("");
+    if dir.starts_with('/') || first_component == "." || first_component == ".." {
+        return None;
+    }
+    let cdpath = shell.variables.get("CDPATH")?;
+    cdpath.split(':').find_map(|entry| {
+        // an empty entry is the working directory
+        let candidate = if entry.is_empty() { String::from(dir) } else { format!("{}/{}", entry.trim_end_matches('/'), dir) };
+        Path::new(&candidate).is_dir().then_some((candidate, !entry.is_empty()))
+    })
+}
+
+// The cd builtin: without a directory go to HOME, 'cd -' goes back to OLDPWD. -P resolves the symbolic links of the
+// new working directory, -L (the default) keeps them
+pub fn cd(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let mut physical = false;
+    let mut operands = args;
+    while let Some(option) = operands.first().filter(|a| a.starts_with('-') && a.len() > 1) {
+        operands = &operands[1..];
+        if option == "--" {
+            break;
+        }
+        for c in option.chars().skip(1) {
+            match c {
+                'L' => physical = false,
+                'P' => physical = true,
+                _ => {
+                    writeln!(streams.stderr, "cd: -{}: invalid option", c)?;
+                    writeln!(streams.stderr, "cd: usage: cd [-L|-P] [dir]")?;
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    if operands.len() > 1 {
+        writeln!(streams.stderr, "cd: too many arguments")?;
+        return Ok(1);
+    }
+    let (dir, mut print_dir) = match operands.first().map(|s| s.as_str()) {
+        None => match shell.variables.get("HOME") {
+            Some(home) => (String::from(home), false),
+            None => {
+                writeln!(streams.stderr, "cd: HOME not set")?;
+                return Ok(1);
+            }
+        },
+        Some("-") => match shell.variables.get("OLDPWD") {
+            Some(old_pwd) => (String::from(old_pwd), true),
+            None => {
+                writeln!(streams.stderr, "cd: OLDPWD not set")?;
+                return Ok(1);
+            }
+        },
+        Some(dir) => (String::from(dir), false)
+    };
+    // an empty directory leaves the working directory as it is
+    if dir.is_empty() {
+        return Ok(0);
+    }
+    let target = match search_cdpath(shell, &dir) {
+        Some((found, from_cdpath)) => {
+            print_dir |= from_cdpath;
+            found
+        },
+        None => dir.clone()
+    };
+    match change_working_dir(shell, &target, physical) {
+        Ok(()) => {
+            if print_dir {
+                writeln!(streams.stdout, "{}", shell.working_dir())?;
+            }
+            Ok(0)
+        },
+        Err(e) => {
+            writeln!(streams.stderr, "cd: {}: {}", dir, error_message(&e))?;
+            Ok(1)
+        }
+    }
+}
+
+// The directory stack of pushd, popd and dirs: the working directory and the saved directories, the top first
+fn dir_stack(shell: &Shell) -> Vec<String> {
+    std::iter::once(shell.working_dir()).chain(shell.dir_stack.iter().cloned()).collect()
+}
+
+// The index that '+N' (counting from the top of the stack, starting at 0) or '-N' (counting from the bottom) refers to.
+// None if the argument isn't of this form
+fn stack_index(builtin: &str, arg: &str, len: usize) -> Option<Result<usize, String>> {
+    let digits = arg.strip_prefix('+').or_else(|| arg.strip_prefix('-')).filter(|d| !d.is_empty() && d.chars().all(|c| c.is_ascii_digit()))?;
+    let n = digits.parse::<usize>().unwrap_or(usize::MAX);
+    let index = if arg.starts_with('+') { Some(n) } else { (len - 1).checked_sub(n) };
+    Some(index.filter(|i| *i < len).ok_or_else(|| format!("{}: {}: directory stack index out of range", builtin, arg)))
+}
+
+// Print the directory stack on one line, the home directory shown as '~'
+fn print_dir_stack(shell: &Shell, streams: &mut BuiltinIo) -> io::Result<()> {
+    let stack: Vec<String> = dir_stack(shell).iter().map(|dir| shorten_home(shell, dir)).collect();
+    writeln!(streams.stdout, "{}", stack.join(" "))
+}
+
+fn shorten_home(shell: &Shell, dir: &str) -> String {
+    match shell.variables.get("HOME").filter(|home| !home.is_empty() && *home != "/") {
+        Some(home) if dir == home => String::from("~"),
+        Some(home) if dir.starts_with(&format!("{}/", home)) => format!("~{}", &dir[home.len()..]),
+        _ => String::from(dir)
+    }
+}
+
+// Split off the -n option of pushd and popd, it keeps the working directory and changes only the stack
+fn split_no_change(args: &[String]) -> (bool, &[String]) {
+    match args.first() {
+        Some(first) if first == "-n" => (true, &args[1..]),
+        _ => (false, args)
+    }
+}
+
+// Make the stack the new directory stack, changing to its top directory first if cd is set
+fn set_dir_stack(shell: &mut Shell, builtin: &str, mut stack: Vec<String>, cd: bool, streams: &mut BuiltinIo) -> io::Result<i32> {
+    if cd {
+        if let Err(e) = change_working_dir(shell, &stack[0], false) {
+            writeln!(streams.stderr, "{}: {}: {}", builtin, stack[0], error_message(&e))?;
+            return Ok(1);
+        }
+        stack[0] = shell.working_dir();
+    }
+    shell.dir_stack = stack.split_off(1);
+    print_dir_stack(shell, streams)?;
+    Ok(0)
+}
+
+// The pushd builtin: save the working directory on the stack and change to the directory. Without a directory the two
+// top directories are swapped, '+N' or '-N' rotates the stack to bring that directory to the top.
+// -n leaves the working directory at the top and works on the saved directories only: the directory is added under
+// the top, the swap and the rotation skip the top
+pub fn pushd(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (no_change, args) = split_no_change(args);
+    let mut stack = dir_stack(shell);
+    // the part of the stack that is swapped or rotated
+    let first = no_change as usize;
+    match args {
+        [] if stack.len() < first + 2 => {
+            writeln!(streams.stderr, "pushd: no other directory")?;
+            return Ok(1);
+        },
+        [] => stack.swap(first, first + 1),
+        [arg] => match stack_index("pushd", arg, stack.len()) {
+            Some(Ok(index)) => stack[first..].rotate_left(index.saturating_sub(first)),
+            Some(Err(e)) => {
+                writeln!(streams.stderr, "{}", e)?;
+                return Ok(1);
+            },
+            None if no_change => stack.insert(1, logical_path(&shell.working_dir(), arg)),
+            None => stack.insert(0, arg.clone())
+        },
+        _ => {
+            writeln!(streams.stderr, "pushd: too many arguments")?;
+            return Ok(1);
+        }
+    }
+    set_dir_stack(shell, "pushd", stack, !no_change, streams)
+}
+
+// The popd builtin: remove the top directory from the stack and change to the next one. '+N' or '-N' removes that
+// directory instead, -n removes the one under the top without changing the working directory
+pub fn popd(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (no_change, args) = split_no_change(args);
+    let mut stack = dir_stack(shell);
+    if stack.len() < 2 {
+        writeln!(streams.stderr, "popd: directory stack empty")?;
+        return Ok(1);
+    }
+    let index = match args {
+        [] => 0,
+        [arg] => match stack_index("popd", arg, stack.len()) {
+            Some(Ok(index)) => index,
+            Some(Err(e)) => {
+                writeln!(streams.stderr, "{}", e)?;
+                return Ok(1);
+            },
+            None => {
+                writeln!(streams.stderr, "popd: {}: invalid argument", arg)?;
+                return Ok(2);
+            }
+        },
+        _ => {
+            writeln!(streams.stderr, "popd: too many arguments")?;
+            return Ok(1);
+        }
+    };
+    let index = if no_change && index == 0 { 1 } else { index };
+    stack.remove(index);
+    set_dir_stack(shell, "popd", stack, index == 0, streams)
+}
+
+// The dirs builtin: list the directory stack. -c clears it, -l shows the home directory in full, -p prints one directory
+// per line and -v numbers them. '+N' or '-N' shows only that directory
+pub fn dirs(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (mut long, mut per_line, mut numbered) = (false, false, false);
+    let mut selected = None;
+    let stack = dir_stack(shell);
+    for arg in args {
+        match arg.as_str() {
+            "-c" => {
+                shell.dir_stack.clear();
+                return Ok(0);
+            },
+            "-l" => long = true,
+            "-p" => per_line = true,
+            "-v" => (per_line, numbered) = (true, true),
+            _ => match stack_index("dirs", arg, stack.len()) {
+                Some(Ok(index)) => selected = Some(index),
+                Some(Err(e)) => {
+                    writeln!(streams.stderr, "{}", e)?;
+                    return Ok(1);
+                },
+                None => {
+                    writeln!(streams.stderr, "dirs: {}: invalid option", arg)?;
+                    writeln!(streams.stderr, "dirs: usage: dirs [-clpv] [+N] [-N]")?;
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    let entries: Vec<(usize, String)> = stack.iter().enumerate()
+        .filter(|(i, _)| selected.is_none_or(|s| s == *i))
+        .map(|(i, dir)| (i, if long { dir.clone() } else { shorten_home(shell, dir) }))
+        .collect();
+    if numbered {
+        for (i, dir) in entries {
+            writeln!(streams.stdout, "{:2}  {}", i, dir)?;
+        }
+    } else if per_line {
+        for (_, dir) in entries {
+            writeln!(streams.stdout, "{}", dir)?;
+        }
+    } else {
+        let dirs: Vec<String> = entries.into_iter().map(|(_, dir)| dir).collect();
+        writeln!(streams.stdout, "{}", dirs.join(" "))?;
+    }
+    Ok(0)
+}
+
+// The exit builtin: stop the interpreter after the current command. The exit status is the argument (modulo 256),
+// by default the last status
+pub fn exit(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let status = match args {
+        [] => shell.last_status,
+        [arg] => match arg.parse::<i64>() {
+            Ok(status) => (status & 0xff) as i32,
+            Err(_) => {
+                writeln!(streams.stderr, "exit: {}: numeric argument required", arg)?;
+                2
+            }
+        },
+        _ => {
+            writeln!(streams.stderr, "exit: too many arguments")?;
+            return Ok(1);
+        }
+    };
+    shell.exit_requested = true;
+    Ok(status)
+}
+
+// Quote a value so that it can be read back by the shell
+fn quote_value(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Set the variables given as NAME or NAME=value and mark them with mark. Without arguments (or with -p) the marked variables
+// are listed in a form that can be read back
+fn declare(shell: &mut Shell, builtin: &str, args: &[String], mark: fn(&mut Shell, &str), is_marked: fn(&Variable) -> bool, streams: &mut BuiltinIo) -> io::Result<i32> {
+    if args.is_empty() || args == ["-p"] {
+        for (name, variable) in shell.variables.sorted().into_iter().filter(|(_, v)| is_marked(v)) {
+            match &variable.value {
+                Some(value) => writeln!(streams.stdout, "{} {}={}", builtin, name, quote_value(value))?,
+                None => writeln!(streams.stdout, "{} {}", builtin, name)?
+            }
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match split_assignment(arg) {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None)
+        };
+        if !is_valid_name(name) {
+            writeln!(streams.stderr, "{}: `{}': not a valid identifier", builtin, arg)?;
+            status = 1;
+            continue;
+        }
+        if let Some(value) = value {
+            if let Err(e) = shell.variables.set(name, value) {
+                writeln!(streams.stderr, "{}: {}", builtin, e)?;
+                status = 1;
+                continue;
+            }
+        }
+        mark(shell, name);
+    }
+    Ok(status)
+}
+
+// The export builtin: the variables are given to the programs the shell runs
+pub fn export(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    declare(shell, "export", args, |shell, name| shell.variables.export(name), |v| v.exported, streams)
+}
+
+// The readonly builtin: the variables can't be changed or unset anymore
+pub fn readonly(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    declare(shell, "readonly", args, |shell, name| shell.variables.set_readonly(name), |v| v.readonly, streams)
+}
+
+// The unset builtin: remove the variables. -v (the default) is accepted for compatibility
+pub fn unset(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let mut status = 0;
+    let functions = args.first().is_some_and(|a| a == "-f");
+    for name in args.iter().skip_while(|a| *a == "-v" || *a == "-f") {
+        if functions {
+            shell.functions.remove(name);
+        } else if !is_valid_name(name) {
+            writeln!(streams.stderr, "unset: `{}': not a valid identifier", name)?;
+            status = 1;
+        } else if let Err(e) = shell.variables.unset(name) {
+            writeln!(streams.stderr, "unset: {}", e)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+// The shopt builtin: -s sets the options, -u unsets them. Without a flag the options are listed, the status tells
+// whether all the given ones are set
+pub fn shopt(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (value, names) = match args.first().map(|s| s.as_str()) {
+        Some("-s") => (Some(true), &args[1..]),
+        Some("-u") => (Some(false), &args[1..]),
+        _ => (None, args)
+    };
+    let names: Vec<&str> = if names.is_empty() {
+        ShellOptions::NAMES.to_vec()
+    } else {
+        names.iter().map(|s| s.as_str()).collect()
+    };
+    let mut status = 0;
+    for name in names {
+        let Some(option) = shell.options.get_mut(name) else {
+            writeln!(streams.stderr, "shopt: {}: invalid shell option name", name)?;
+            status = 1;
+            continue;
+        };
+        match value {
+            Some(value) => *option = value,
+            None => {
+                writeln!(streams.stdout, "{:<16}{}", name, if *option { "on" } else { "off" })?;
+                if !*option && !args.is_empty() {
+                    status = 1;
+                }
+            }
+        }
+    }
+    Ok(status)
+}
+
+// The let builtin: evaluate each argument as an arithmetic expression. The status is 0 if the value of the last one is not zero
+pub fn let_expressions(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    if args.is_empty() {
+        writeln!(streams.stderr, "let: expression expected")?;
+        return Ok(1);
+    }
+    let mut value = 0;
+    for arg in args {
+        match arithmetic::evaluate(shell, arg) {
+            Ok(v) => value = v,
+            Err(e) => {
+                writeln!(streams.stderr, "let: {}", e)?;
+                return Ok(1);
+            }
+        }
+    }
+    Ok((value == 0) as i32)
+}
+
+// The echo builtin: print the arguments separated by spaces. -n leaves out the final newline, -e expands the backslash
+// escapes and -E (the default) doesn't. Only the arguments made of these letters are options
+pub fn echo(_shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (mut newline, mut escapes) = (true, false);
+    let mut words = args;
+    while let Some(option) = words.first().filter(|a| a.len() > 1 && a.starts_with('-') && a[1..].chars().all(|c| "neE".contains(c))) {
+        for c in option[1..].chars() {
+            match c {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false
+            }
+        }
+        words = &words[1..];
+    }
+    let mut text = words.join(" ");
+    if escapes {
+        let (expanded, stop) = printf::expand_escapes(&text, true);
+        // \c stops the output, the newline included
+        text = expanded;
+        newline &= !stop;
+    }
+    if newline {
+        text.push('\n');
+    }
+    streams.stdout.write_all(text.as_bytes())?;
+    Ok(0)
+}
+
+// The printf builtin: format the arguments like printf(3), reusing the format until all of them are consumed.
+// -v NAME assigns the output to the variable instead of printing it
+pub fn printf(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (variable, args) = match args {
+        [option, name, rest @ ..] if option == "-v" => (Some(name.as_str()), rest),
+        _ => (None, args)
+    };
+    let args = if args.first().is_some_and(|a| a == "--") { &args[1..] } else { args };
+    let Some((format, args)) = args.split_first() else {
+        writeln!(streams.stderr, "printf: usage: printf [-v var] format [arguments]")?;
+        return Ok(2);
+    };
+    let (output, errors) = printf::format(format, args);
+    for error in &errors {
+        writeln!(streams.stderr, "printf: {}", error)?;
+    }
+    let status = if errors.is_empty() { 0 } else { 1 };
+    match variable {
+        Some(name) => {
+            if let Err(e) = shell.variables.set(name, &output) {
+                writeln!(streams.stderr, "printf: {}", e)?;
+                return Ok(1);
+            }
+        },
+        None => streams.stdout.write_all(output.as_bytes())?
+    }
+    Ok(status)
+}
+
+// The pwd builtin: print the working directory. -L (the default) keeps the symbolic links cd went through, -P resolves them
+pub fn pwd(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let mut physical = false;
+    for arg in args {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            _ => {
+                writeln!(streams.stderr, "pwd: {}: invalid option", arg)?;
+                writeln!(streams.stderr, "pwd: usage: pwd [-LP]")?;
+                return Ok(2);
+            }
+        }
+    }
+    let dir = if physical {
+        current_dir().map(|dir| dir.to_string_lossy().into_owned())
+    } else {
+        Ok(shell.working_dir())
+    };
+    match dir {
+        Ok(dir) => {
+            writeln!(streams.stdout, "{}", dir)?;
+            Ok(0)
+        },
+        Err(e) => {
+            writeln!(streams.stderr, "pwd: {}", e)?;
+            Ok(1)
+        }
+    }
+}
+
+pub fn true_builtin(_shell: &mut Shell, _args: &[String], _streams: &mut BuiltinIo) -> io::Result<i32> {
+    Ok(0)
+}
+
+pub fn false_builtin(_shell: &mut Shell, _args: &[String], _streams: &mut BuiltinIo) -> io::Result<i32> {
+    Ok(1)
 }
 
-fn change_working_dir(path_str: &str) -> io::Result<()> {
-    let path = Path::new(path_str);
-    set_current_dir(path)?;
-    Ok(())
+// What a command name refers to, in the order the executor looks them up
+enum CommandKind {
+    // a reserved word like 'if'
+    Keyword,
+    // a function with its definition
+    Function(String),
+    Builtin,
+    // an executable file, found in PATH or given by a path
+    File(String)
 }
 
-fn split_by_whitespace(input: &str) -> Vec<&str> {
-    input.split_ascii_whitespace().collect()
+// Whether the path is a regular file someone may execute
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
 }
 
-// Check if the given input string is a built in command. Return information about the type of command via the enum.
-pub fn check_builtin(input: &str) -> io::Result<BUILTINS> {
-    if input.eq("exit") {
-        return Ok(BUILTINS::EXIT);
+// The executable files the name refers to: the name itself if it contains a '/', otherwise the files with this name
+// in the directories of PATH (an empty entry is the working directory), in order
+fn find_in_path(shell: &Shell, name: &str) -> Vec<String> {
+    if name.contains('/') {
+        return if is_executable(Path::new(name)) { vec![String::from(name)] } else { Vec::new() };
     }
-    let parts = split_by_whitespace(input);
-    if parts.len() >= 2 && parts[0].eq("cd") {
-        if let Ok(_) = change_working_dir(parts[1]) {
-            return Ok(BUILTINS::CD);
+    let path = shell.variables.get("PATH").unwrap_or("");
+    path.split(':')
+        .map(|dir| if dir.is_empty() { String::from(name) } else { format!("{}/{}", dir.trim_end_matches('/'), name) })
+        .filter(|candidate| is_executable(Path::new(candidate)))
+        .collect()
+}
+
+// Everything the name refers to, the one the executor would run first. Only the first executable file unless all is set
+fn resolve_command(shell: &Shell, name: &str, all: bool) -> Vec<CommandKind> {
+    let mut kinds = Vec::new();
+    if RESERVED_WORDS.contains(&name) {
+        kinds.push(CommandKind::Keyword);
+    }
+    if let Some(body) = shell.functions.get(name) {
+        kinds.push(CommandKind::Function(format!("{}() {{ {}; }}", name, body)));
+    }
+    if is_builtin(name) && !name.contains('/') {
+        kinds.push(CommandKind::Builtin);
+    }
+    kinds.extend(find_in_path(shell, name).into_iter().map(CommandKind::File));
+    if !all {
+        kinds.truncate(1);
+    }
+    kinds
+}
+
+// Describe what the name refers to like type does, e.g 'ls is /usr/bin/ls'
+fn describe_command(name: &str, kind: &CommandKind) -> String {
+    match kind {
+        CommandKind::Keyword => format!("{} is a shell keyword", name),
+        CommandKind::Function(definition) => format!("{} is a function\n{}", name, definition),
+        CommandKind::Builtin => format!("{} is a shell builtin", name),
+        CommandKind::File(path) => format!("{} is {}", name, path)
+    }
+}
+
+// The type builtin: tell what each name refers to. -t prints only the kind (builtin or file), -p only the path of
+// a file and -P searches PATH even for a builtin. -a shows everything the name refers to, not only the first one
+pub fn type_builtin(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (mut all, mut kind_only, mut path_only, mut force_path) = (false, false, false, false);
+    let mut names = args;
+    while let Some(option) = names.first().filter(|a| a.len() > 1 && a.starts_with('-')) {
+        names = &names[1..];
+        if option == "--" {
+            break;
+        }
+        for c in option[1..].chars() {
+            match c {
+                'a' => all = true,
+                't' => kind_only = true,
+                'p' => path_only = true,
+                'P' => force_path = true,
+                _ => {
+                    writeln!(streams.stderr, "type: -{}: invalid option", c)?;
+                    writeln!(streams.stderr, "type: usage: type [-afptP] name [name ...]")?;
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    let mut status = 0;
+    for name in names {
+        let mut kinds = resolve_command(shell, name, true);
+        if force_path {
+            kinds.retain(|kind| matches!(kind, CommandKind::File(_)));
+        }
+        if !all {
+            kinds.truncate(1);
+        }
+        if kinds.is_empty() {
+            if !kind_only && !path_only && !force_path {
+                writeln!(streams.stderr, "type: {}: not found", name)?;
+            }
+            status = 1;
+            continue;
+        }
+        for kind in &kinds {
+            match kind {
+                CommandKind::File(path) if path_only || force_path => writeln!(streams.stdout, "{}", path)?,
+                _ if path_only => (),
+                CommandKind::Keyword if kind_only => writeln!(streams.stdout, "keyword")?,
+                CommandKind::Function(_) if kind_only => writeln!(streams.stdout, "function")?,
+                CommandKind::Builtin if kind_only => writeln!(streams.stdout, "builtin")?,
+                CommandKind::File(_) if kind_only => writeln!(streams.stdout, "file")?,
+                _ => writeln!(streams.stdout, "{}", describe_command(name, kind))?
+            }
+        }
+    }
+    Ok(status)
+}
+
+// The command builtin with -v or -V: tell what each name refers to, -v briefly (the name of a builtin, the path of a file)
+// and -V like type. Running 'command name args' (the name is then never a function) is handled by the executor
+pub fn command(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let mut verbose = None;
+    let mut names = args;
+    while let Some(option) = names.first().filter(|a| a.len() > 1 && a.starts_with('-')) {
+        names = &names[1..];
+        if option == "--" {
+            break;
         }
+        for c in option[1..].chars() {
+            match c {
+                'v' => verbose = Some(false),
+                'V' => verbose = Some(true),
+                'p' => (),
+                _ => {
+                    writeln!(streams.stderr, "command: -{}: invalid option", c)?;
+                    writeln!(streams.stderr, "command: usage: command [-pVv] command [arg ...]")?;
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    let Some(verbose) = verbose else {
+        // 'command' alone or with only -p
+        return Ok(0);
+    };
+    let mut status = 0;
+    for name in names {
+        match resolve_command(shell, name, false).first() {
+            Some(kind) if verbose => writeln!(streams.stdout, "{}", describe_command(name, kind))?,
+            Some(CommandKind::Keyword | CommandKind::Function(_) | CommandKind::Builtin) => writeln!(streams.stdout, "{}", name)?,
+            Some(CommandKind::File(path)) => writeln!(streams.stdout, "{}", path)?,
+            None => {
+                if verbose {
+                    writeln!(streams.stderr, "command: {}: not found", name)?;
+                }
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+// The which builtin: print the path of the program each name runs, searching PATH. -a prints all the matching files
+pub fn which(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let (all, names) = match args.first() {
+        Some(first) if first == "-a" => (true, &args[1..]),
+        _ => (false, args)
+    };
+    let mut status = 0;
+    for name in names {
+        let files = find_in_path(shell, name);
+        if files.is_empty() {
+            status = 1;
+        }
+        for file in files.iter().take(if all { files.len() } else { 1 }) {
+            writeln!(streams.stdout, "{}", file)?;
+        }
+    }
+    Ok(status)
+}
+
+// The test builtin: the status is 0 if the conditional expression made of the arguments is true, 1 if it is false
+// and 2 if it is not valid
+pub fn test(_shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    evaluate_test("test", args, streams)
+}
+
+// The [ builtin, test with a closing ']' as the last argument
+pub fn bracket(_shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    match args.split_last() {
+        Some((last, args)) if last == "]" => evaluate_test("[", args, streams),
+        _ => {
+            writeln!(streams.stderr, "[: missing ']'")?;
+            Ok(2)
+        }
+    }
+}
+
+fn evaluate_test(builtin: &str, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    match conditional::test(args) {
+        Ok(value) => Ok((!value) as i32),
+        Err(e) => {
+            writeln!(streams.stderr, "{}: {}", builtin, e)?;
+            Ok(2)
+        }
+    }
+}
+
+// The number of loops break or continue leaves, 1 without an argument. More loops than there are means all of them
+fn loop_levels(shell: &Shell, builtin: &str, args: &[String], streams: &mut BuiltinIo) -> io::Result<Option<usize>> {
+    if shell.loop_depth == 0 {
+        writeln!(streams.stderr, "{}: only meaningful in a 'for', 'while', or 'until' loop", builtin)?;
+        return Ok(None);
+    }
+    let levels = match args {
+        [] => 1,
+        [levels] => match levels.parse::<i64>() {
+            Ok(levels) if levels > 0 => levels as usize,
+            Ok(_) => {
+                writeln!(streams.stderr, "{}: {}: loop count out of range", builtin, levels)?;
+                return Ok(None);
+            },
+            Err(_) => {
+                writeln!(streams.stderr, "{}: {}: numeric argument required", builtin, levels)?;
+                return Ok(None);
+            }
+        },
+        _ => {
+            writeln!(streams.stderr, "{}: too many arguments", builtin)?;
+            return Ok(None);
+        }
+    };
+    Ok(Some(levels.min(shell.loop_depth)))
+}
+
+// The break builtin: leave the innermost loop, or N loops with 'break N'
+pub fn break_builtin(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    match loop_levels(shell, "break", args, streams)? {
+        Some(levels) => {
+            shell.control_flow = Some(ControlFlow::Break(levels));
+            Ok(0)
+        },
+        None => Ok(1)
+    }
+}
+
+// The continue builtin: go on with the next iteration of the innermost loop, or of the Nth loop with 'continue N'
+pub fn continue_builtin(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    match loop_levels(shell, "continue", args, streams)? {
+        Some(levels) => {
+            shell.control_flow = Some(ControlFlow::Continue(levels));
+            Ok(0)
+        },
+        None => Ok(1)
     }
-    Ok(BUILTINS::NONE)
-}
\ No newline at end of file
+}
+
+// The return builtin: leave the running function or sourced file with the status N, or with the status of the last command
+pub fn return_builtin(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    if shell.function_frames.is_empty() && shell.source_depth == 0 {
+        writeln!(streams.stderr, "return: can only 'return' from a function or sourced script")?;
+        return Ok(1);
+    }
+    let status = match args {
+        [] => shell.last_status,
+        [status] => match status.parse::<i64>() {
+            Ok(status) => (status & 0xff) as i32,
+            Err(_) => {
+                writeln!(streams.stderr, "return: {}: numeric argument required", status)?;
+                2
+            }
+        },
+        _ => {
+            writeln!(streams.stderr, "return: too many arguments")?;
+            return Ok(1);
+        }
+    };
+    shell.control_flow = Some(ControlFlow::Return);
+    Ok(status)
+}
+
+// The local builtin: make the variables local to the running function, NAME=value also sets them. A local variable
+// hides the variable of the same name until the function returns, the functions it calls see the local one
+pub fn local(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let Some(FunctionFrame { saved_variables }) = shell.function_frames.last_mut() else {
+        writeln!(streams.stderr, "local: can only be used in a function")?;
+        return Ok(1);
+    };
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match split_assignment(arg) {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None)
+        };
+        if !is_valid_name(name) {
+            writeln!(streams.stderr, "local: `{}': not a valid identifier", arg)?;
+            status = 1;
+            continue;
+        }
+        if !saved_variables.iter().any(|(saved, _)| saved == name) {
+            saved_variables.push((String::from(name), shell.variables.save(name)));
+        }
+        let result = match value {
+            Some(value) => shell.variables.set(name, value),
+            None => shell.variables.unset(name)
+        };
+        if let Err(e) = result {
+            writeln!(streams.stderr, "local: {}", e)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+// The shift builtin: drop the first N positional parameters (1 without an argument), $N+1 becomes $1
+pub fn shift(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let count = match args {
+        [] => 1,
+        [count] => match count.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                writeln!(streams.stderr, "shift: {}: numeric argument required", count)?;
+                return Ok(1);
+            }
+        },
+        _ => {
+            writeln!(streams.stderr, "shift: too many arguments")?;
+            return Ok(1);
+        }
+    };
+    if count > shell.positional.len() {
+        return Ok(1);
+    }
+    shell.positional.drain(..count);
+    Ok(0)
+}
+
+// The deepest the source builtin can be nested, a file that sources itself fails instead of overflowing the stack
+const MAX_SOURCE_DEPTH: usize = 1000;
+
+// The file the source builtin reads: the name itself if it contains a '/', otherwise the first file with this name in
+// the directories of PATH, then the one in the working directory
+fn find_source_file(shell: &Shell, name: &str) -> Option<String> {
+    if name.contains('/') {
+        return Some(String::from(name));
+    }
+    let path = shell.variables.get("PATH").unwrap_or("");
+    path.split(':')
+        .map(|dir| if dir.is_empty() { String::from(name) } else { format!("{}/{}", dir.trim_end_matches('/'), name) })
+        .chain(std::iter::once(String::from(name)))
+        .find(|candidate| Path::new(candidate).is_file())
+}
+
+// The source builtin, also named '.': run the commands of a file in the interpreter itself, so the variables and
+// functions it sets stay. The arguments after the file are the positional parameters while it runs, return leaves it.
+// Returns the status of the last command of the file
+pub fn source(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let Some((name, file_args)) = args.split_first() else {
+        writeln!(streams.stderr, "source: filename argument required")?;
+        return Ok(2);
+    };
+    if shell.source_depth >= MAX_SOURCE_DEPTH {
+        writeln!(streams.stderr, "source: {}: maximum source nesting level exceeded ({})", name, MAX_SOURCE_DEPTH)?;
+        return Ok(1);
+    }
+    let Some(path) = find_source_file(shell, name) else {
+        writeln!(streams.stderr, "source: {}: file not found", name)?;
+        return Ok(1);
+    };
+    let list = match fs::read_to_string(&path) {
+        Ok(contents) => match tokenize(&contents).and_then(parse) {
+            Ok(list) => list,
+            Err(e) => {
+                writeln!(streams.stderr, "{}: {}", name, e)?;
+                return Ok(2);
+            }
+        },
+        Err(e) => {
+            writeln!(streams.stderr, "source: {}: {}", name, error_message(&e))?;
+            return Ok(1);
+        }
+    };
+    let positional = (!file_args.is_empty()).then(|| std::mem::replace(&mut shell.positional, file_args.to_vec()));
+    shell.source_depth += 1;
+    let mut status = command_execution::execute_list(shell, &list);
+    if shell.control_flow == Some(ControlFlow::Return) {
+        shell.control_flow = None;
+        status = shell.last_status;
+    }
+    shell.source_depth -= 1;
+    if let Some(positional) = positional {
+        shell.positional = positional;
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Mutex;
+
+    // the working directory belongs to the whole process, the tests that change it run one at a time
+    static WORKING_DIR: Mutex<()> = Mutex::new(());
+
+    // Run the test in a directory tree of its own: a/b and link -> a/b. The shell starts at the top of the tree and
+    // the original working directory is restored afterwards
+    fn in_test_dir(name: &str, test: impl FnOnce(&mut Shell, &str)) {
+        let _guard = WORKING_DIR.lock().unwrap_or_else(|e| e.into_inner());
+        let original = current_dir().unwrap();
+        let root = std::env::temp_dir().join(format!("versio2-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        std::os::unix::fs::symlink(root.join("a/b"), root.join("link")).unwrap();
+        let root = root.canonicalize().unwrap().to_string_lossy().into_owned();
+        let mut shell = Shell::make(None);
+        set_current_dir(&root).unwrap();
+        shell.variables.set("PWD", &root).unwrap();
+        test(&mut shell, &root);
+        set_current_dir(original).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // Run the builtin named by the first word through the registry, returning the status and the output
+    fn run(shell: &mut Shell, words: &[&str]) -> (i32, String) {
+        let argv: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let mut streams = BuiltinIo { stdin: &mut io::empty(), stdout: &mut stdout, stderr: &mut stderr };
+        let status = find(words[0]).unwrap().run(shell, &argv, &mut streams).unwrap();
+        (status, String::from_utf8(stdout).unwrap())
+    }
+
+    #[test]
+    fn logical_paths() {
+        assert_eq!(logical_path("/a/b", "../c/./d"), "/a/c/d");
+        assert_eq!(logical_path("/a", "/x/../y"), "/y");
+        assert_eq!(logical_path("/", ".."), "/");
+    }
+
+    #[test]
+    fn error_messages_leave_out_the_error_number() {
+        assert_eq!(error_message(&io::Error::from_raw_os_error(libc::ENOENT)), "No such file or directory");
+        assert_eq!(error_message(&io::Error::from_raw_os_error(libc::ENOTDIR)), "Not a directory");
+        assert_eq!(error_message(&io::Error::from_raw_os_error(libc::ELOOP)), "Too many levels of symbolic links");
+        assert_eq!(error_message(&io::Error::other("custom")), "custom");
+    }
+
+    #[test]
+    fn cd_keeps_the_symbolic_links() {
+        in_test_dir("cd-links", |shell, root| {
+            assert_eq!(run(shell, &["cd", "link"]).0, 0);
+            assert_eq!(shell.variables.get("PWD"), Some(format!("{}/link", root).as_str()));
+            assert_eq!(shell.variables.get("OLDPWD"), Some(root));
+            assert_eq!(run(shell, &["cd", ".."]).0, 0);
+            assert_eq!(shell.working_dir(), root);
+            assert_eq!(run(shell, &["cd", "-P", "link"]).0, 0);
+            assert_eq!(shell.working_dir(), format!("{}/a/b", root));
+            assert_eq!(current_dir().unwrap().to_string_lossy(), format!("{}/a/b", root));
+        });
+    }
+
+    #[test]
+    fn cd_home_back_and_cdpath() {
+        in_test_dir("cd-home", |shell, root| {
+            shell.variables.set("HOME", &format!("{}/a", root)).unwrap();
+            assert_eq!(run(shell, &["cd"]).0, 0);
+            assert_eq!(shell.working_dir(), format!("{}/a", root));
+            assert_eq!(run(shell, &["cd", "-"]).0, 0);
+            assert_eq!(shell.working_dir(), root);
+            shell.variables.set("CDPATH", &format!(":{}/a", root)).unwrap();
+            assert_eq!(run(shell, &["cd", "b"]).0, 0);
+            assert_eq!(shell.working_dir(), format!("{}/a/b", root));
+            assert_eq!(run(shell, &["cd", "missing"]).0, 1);
+            assert_eq!(run(shell, &["cd", "a", "b"]).0, 1);
+            assert_eq!(run(shell, &["cd", "-x"]).0, 2);
+            shell.variables.unset("HOME").unwrap();
+            assert_eq!(run(shell, &["cd"]).0, 1);
+        });
+    }
+
+    #[test]
+    fn stack_indexes() {
+        assert_eq!(stack_index("dirs", "+0", 3), Some(Ok(0)));
+        assert_eq!(stack_index("dirs", "-0", 3), Some(Ok(2)));
+        assert_eq!(stack_index("dirs", "+2", 3), Some(Ok(2)));
+        assert!(matches!(stack_index("dirs", "+3", 3), Some(Err(_))));
+        assert!(matches!(stack_index("dirs", "-3", 3), Some(Err(_))));
+        assert_eq!(stack_index("dirs", "dir", 3), None);
+        assert_eq!(stack_index("dirs", "-", 3), None);
+    }
+
+    #[test]
+    fn pushd_and_popd() {
+        in_test_dir("pushd", |shell, root| {
+            let a = format!("{}/a", root);
+            let b = format!("{}/a/b", root);
+            assert_eq!(run(shell, &["pushd", "a"]).0, 0);
+            assert_eq!(run(shell, &["pushd", "b"]).0, 0);
+            assert_eq!(dir_stack(shell), [b.clone(), a.clone(), String::from(root)]);
+            assert_eq!(run(shell, &["pushd"]).0, 0);
+            assert_eq!(dir_stack(shell), [a.clone(), b.clone(), String::from(root)]);
+            assert_eq!(run(shell, &["pushd", "+2"]).0, 0);
+            assert_eq!(dir_stack(shell), [String::from(root), a.clone(), b.clone()]);
+            assert_eq!(current_dir().unwrap().to_string_lossy(), root);
+            assert_eq!(run(shell, &["pushd", "-n", "a"]).0, 0);
+            assert_eq!(dir_stack(shell), [String::from(root), a.clone(), a.clone(), b.clone()]);
+            assert_eq!(run(shell, &["pushd", "+4"]).0, 1);
+            assert_eq!(run(shell, &["popd", "-0"]).0, 0);
+            assert_eq!(run(shell, &["popd", "-n"]).0, 0);
+            assert_eq!(dir_stack(shell), [String::from(root), a.clone()]);
+            assert_eq!(run(shell, &["popd"]).0, 0);
+            assert_eq!(dir_stack(shell), [a.as_str()]);
+            assert_eq!(shell.working_dir(), a);
+            assert_eq!(run(shell, &["popd"]).0, 1);
+            assert_eq!(run(shell, &["pushd"]).0, 1);
+            assert_eq!(run(shell, &["pushd", "missing"]).0, 1);
+            assert_eq!(dir_stack(shell), [a.as_str()]);
+        });
+    }
+
+    #[test]
+    fn pushd_without_changing_the_directory() {
+        in_test_dir("pushd-n", |shell, root| {
+            shell.dir_stack = vec![String::from("/x"), String::from("/y"), String::from("/z")];
+            assert_eq!(run(shell, &["pushd", "-n"]).0, 0);
+            assert_eq!(dir_stack(shell), [root, "/y", "/x", "/z"]);
+            assert_eq!(run(shell, &["pushd", "-n", "+2"]).0, 0);
+            assert_eq!(dir_stack(shell), [root, "/x", "/z", "/y"]);
+            assert_eq!(run(shell, &["pushd", "-n", "-0"]).0, 0);
+            assert_eq!(dir_stack(shell), [root, "/y", "/x", "/z"]);
+            shell.dir_stack.truncate(1);
+            assert_eq!(run(shell, &["pushd", "-n"]).0, 1);
+            assert_eq!(current_dir().unwrap().to_string_lossy(), root);
+        });
+    }
+
+    #[test]
+    fn dirs_clears_the_stack() {
+        in_test_dir("dirs", |shell, root| {
+            shell.variables.set("HOME", root).unwrap();
+            assert_eq!(shorten_home(shell, &format!("{}/a", root)), "~/a");
+            assert_eq!(shorten_home(shell, "/elsewhere"), "/elsewhere");
+            assert_eq!(run(shell, &["pushd", "a"]).0, 0);
+            assert_eq!(run(shell, &["dirs", "-x"]).0, 2);
+            assert_eq!(run(shell, &["dirs", "+5"]).0, 1);
+            assert_eq!(run(shell, &["dirs", "-v"]), (0, String::from(" 0  ~/a\n 1  ~\n")));
+            assert_eq!(run(shell, &["dirs", "-l", "+1"]), (0, format!("{}\n", root)));
+            assert_eq!(run(shell, &["dirs", "-c"]).0, 0);
+            assert_eq!(dir_stack(shell), [format!("{}/a", root)]);
+        });
+    }
+
+    #[test]
+    fn registry() {
+        assert!(is_builtin("cd") && is_builtin("dirs") && !is_builtin("ls"));
+        let mut shell = Shell::make(None);
+        assert_eq!(run(&mut shell, &["exit", "300"]), (44, String::new()));
+        assert!(shell.exit_requested);
+        assert_eq!(run(&mut shell, &["exit", "1", "2"]).0, 1);
+        assert_eq!(run(&mut shell, &["export", "V=a\"b"]).0, 0);
+        assert_eq!(run(&mut shell, &["export"]).1.lines().find(|l| l.starts_with("export V=")), Some("export V=\"a\\\"b\""));
+        assert_eq!(run(&mut shell, &["export", "UNSET_BEFORE"]).0, 0);
+        assert_eq!(run(&mut shell, &["export", "-p"]).1.lines().find(|l| l.contains("UNSET_BEFORE")), Some("export UNSET_BEFORE"));
+        assert_eq!(run(&mut shell, &["let", "x = 2 * 3"]).0, 0);
+        assert_eq!(shell.variables.get("x"), Some("6"));
+        assert_eq!(run(&mut shell, &["let", "x - 6"]).0, 1);
+    }
+
+    #[test]
+    fn echo_and_printf() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run(&mut shell, &["echo", "a", "", "b"]).1, "a  b\n");
+        assert_eq!(run(&mut shell, &["echo", "-n", "-x", "a\\tb"]).1, "-x a\\tb");
+        assert_eq!(run(&mut shell, &["echo", "-ne", "a\\tb\\c", "c"]).1, "a\tb");
+        assert_eq!(run(&mut shell, &["echo", "-eE", "a\\tb"]).1, "a\\tb\n");
+        assert_eq!(run(&mut shell, &["printf", "%s=%d\\n", "a", "1", "b"]), (0, String::from("a=1\nb=0\n")));
+        assert_eq!(run(&mut shell, &["printf", "-v", "V", "%03d", "7"]), (0, String::new()));
+        assert_eq!(shell.variables.get("V"), Some("007"));
+        assert_eq!(run(&mut shell, &["printf", "%d", "x"]).0, 1);
+        assert_eq!(run(&mut shell, &["printf"]).0, 2);
+    }
+
+    #[test]
+    fn pwd_and_type() {
+        in_test_dir("type", |shell, root| {
+            let program = format!("{}/a/prog", root);
+            fs::write(&program, "").unwrap();
+            fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+            fs::write(format!("{}/a/b/prog", root), "").unwrap();
+            fs::copy(&program, format!("{}/a/b/cd", root)).unwrap();
+            shell.variables.set("PATH", &format!("{0}/a/b:{0}/a", root)).unwrap();
+            assert_eq!(run(shell, &["cd", "link"]).0, 0);
+            assert_eq!(run(shell, &["pwd"]).1, format!("{}/link\n", root));
+            assert_eq!(run(shell, &["pwd", "-P"]).1, format!("{}/a/b\n", root));
+            assert_eq!(run(shell, &["type", "cd", "prog"]), (0, format!("cd is a shell builtin\nprog is {}\n", program)));
+            assert_eq!(run(shell, &["type", "-a", "cd"]).1, format!("cd is a shell builtin\ncd is {}/a/b/cd\n", root));
+            assert_eq!(run(shell, &["type", "-t", "cd", "prog"]).1, "builtin\nfile\n");
+            assert_eq!(run(shell, &["type", "-p", "cd"]).1, "");
+            assert_eq!(run(shell, &["type", "-P", "cd"]).1, format!("{}/a/b/cd\n", root));
+            assert_eq!(run(shell, &["type", "missing", "cd"]), (1, String::from("cd is a shell builtin\n")));
+            assert_eq!(run(shell, &["command", "-v", "cd", "prog", "missing"]), (1, format!("cd\n{}\n", program)));
+            assert_eq!(run(shell, &["command", "-V", "prog"]).1, format!("prog is {}\n", program));
+            assert_eq!(run(shell, &["which", "cd"]), (0, format!("{}/a/b/cd\n", root)));
+            assert_eq!(run(shell, &["which", "prog", "missing"]), (1, format!("{}\n", program)));
+        });
+    }
+
+    #[test]
+    fn source_runs_a_file_in_the_interpreter() {
+        in_test_dir("source", |shell, root| {
+            fs::write(format!("{}/a/lib", root), "A=\"$# $1\"; f() { return 4; }\nreturn 3\nA=late").unwrap();
+            shell.variables.set("PATH", &format!("{}/a", root)).unwrap();
+            shell.positional = vec![String::from("outer")];
+            assert_eq!(run(shell, &["source", "lib", "x", "y"]).0, 3);
+            assert_eq!(shell.variables.get("A"), Some("2 x"));
+            assert_eq!(shell.positional, ["outer"]);
+            assert!(shell.functions.contains_key("f") && shell.control_flow.is_none());
+            assert_eq!(run(shell, &[".", "a/lib"]).0, 3);
+            assert_eq!(shell.variables.get("A"), Some("1 outer"));
+            assert_eq!(run(shell, &["return"]).0, 1);
+            assert_eq!(run(shell, &["source", "missing"]).0, 1);
+            assert_eq!(run(shell, &["source"]).0, 2);
+            shell.source_depth = MAX_SOURCE_DEPTH;
+            assert_eq!(run(shell, &["source", "lib"]).0, 1);
+        });
+    }
+}
@@ -1,77 +1,932 @@
-use std::process::{Command, Stdio};
-use std::fs::File;
-use std::io::{Error, ErrorKind};
+use std::process::{self, Stdio};
+use std::io::{self, PipeReader, PipeWriter, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::os::unix::process::CommandExt;
 
+use crate::arithmetic;
+use crate::builtin_commands::{self, Builtin, BuiltinIo};
 use crate::commands::*;
+use crate::conditional;
+use crate::jobs::{self, Job, JobTable};
+use crate::parser;
+use crate::redirection;
+use crate::pattern::Pattern;
+use crate::shell::{ControlFlow, FunctionFrame, Shell};
+use crate::signals;
+use crate::tokenizer;
+use crate::expansion::{expand_assignment_value, expand_pattern, expand_word, expand_word_to_fields};
+use crate::variables::split_assignment;
 
-// take the previous child process struct and the next command in the sequence. Create a new child process struct based on these
-fn advance_pipe_sequence(previous: std::process::Child, current: &SingleCommand) -> std::io::Result<std::process::Child> {
-    let mut proc = Command::new(&current.name);
-    proc.args(&current.args);
-    if let Some(in_red) = &current.in_redirect {
-        // replace stdin with file
-        let file = File::open(in_red)?;
-        proc.stdin(file);
-    } else if let Some(out) = previous.stdout {
-        proc.stdin(out);
-    } 
-    if let Some(out_red) = &current.out_redirect {
-        // replace stdout with file
-        let file = File::create(out_red)?;
-        proc.stdout(file);
-    } else {
-        proc.stdout(Stdio::piped());
+// The exit status of a command that couldn't be found
+const COMMAND_NOT_FOUND: i32 = 127;
+
+// The deepest the function calls can be nested, a deeper call fails instead of overflowing the stack
+const MAX_FUNCTION_DEPTH: usize = 1000;
+
+// A simple command with its assignment values and words expanded
+struct ExpandedCommand {
+    assignments: Vec<(String, String)>,
+    words: Vec<String>,
+    // set for 'command name', the name is never a function then
+    skip_functions: bool
+}
+
+impl ExpandedCommand {
+    // The body of the function the command calls, if its name is a function
+    fn function(&self, shell: &Shell) -> Option<Rc<CommandList>> {
+        let name = self.words.first().filter(|_| !self.skip_functions)?;
+        shell.functions.get(name).cloned()
+    }
+}
+
+fn expand_command(shell: &mut Shell, command: &SingleCommand) -> Result<ExpandedCommand, String> {
+    let mut assignments = Vec::new();
+    for assignment in &command.assignments {
+        assignments.push((assignment.name.clone(), expand_assignment_value(shell, &assignment.value)?));
+    }
+    let mut words = Vec::new();
+    for word in &command.words {
+        let is_declaration = words.first().is_some_and(|name: &String| builtin_commands::DECLARATION_BUILTINS.contains(&name.as_str()));
+        if let Some((name, value)) = split_assignment(word).filter(|_| is_declaration) {
+            words.push(format!("{}={}", name, expand_assignment_value(shell, value)?));
+        } else {
+            words.extend(expand_word_to_fields(shell, word)?);
+        }
     }
+    let skip_functions = strip_command_prefix(&mut words);
+    Ok(ExpandedCommand { assignments, words, skip_functions })
+}
+
+// 'command [-p] name args' runs name like any other command except a function. Only 'command -v/-V' is left to the builtin.
+// Returns whether a prefix was removed
+fn strip_command_prefix(words: &mut Vec<String>) -> bool {
+    let mut stripped = false;
+    while words.first().is_some_and(|word| word == "command") {
+        let options = words[1..].iter().take_while(|word| word.len() > 1 && word.starts_with('-')).count();
+        let only_p = words[1..=options].iter().all(|option| option == "--" || option[1..].chars().all(|c| c == 'p'));
+        if !only_p || words.len() == options + 1 {
+            break;
+        }
+        words.drain(..=options);
+        stripped = true;
+    }
+    stripped
+}
+
+// One stage of a pipeline, ready to be started
+enum Stage<'a> {
+    // a simple command with its words expanded, or the error of the expansion
+    Simple(&'a SingleCommand, Result<ExpandedCommand, String>),
+    // a command the interpreter runs itself, e.g ((...)). In a pipeline it runs in a copy of the interpreter like a builtin
+    Compound(&'a Command)
+}
+
+impl Stage<'_> {
+    // Whether the stage is run by the interpreter rather than by a program: a function, a builtin, assignments or
+    // a compound command other than a subshell
+    fn runs_in_shell(&self, shell: &Shell) -> bool {
+        match self {
+            Stage::Simple(_, Ok(expanded)) => expanded.function(shell).is_some()
+                || expanded.words.first().is_none_or(|name| builtin_commands::is_builtin(name)),
+            Stage::Simple(_, Err(_)) => false,
+            Stage::Compound(Command::Compound(CompoundCommand::Subshell(_), _)) => false,
+            Stage::Compound(_) => true
+        }
+    }
+}
 
-    proc.spawn()
+// Expand all the simple commands of the pipeline before any of them starts
+fn prepare_stages<'a>(shell: &mut Shell, pipeline: &'a Pipeline) -> Vec<Stage<'a>> {
+    shell.substitution_status = None;
+    pipeline.commands.iter().map(|command| match command {
+        Command::Simple(single) => Stage::Simple(single, expand_command(shell, single)),
+        _ => Stage::Compound(command)
+    }).collect()
 }
 
-// pipe the commands in a single pipe group
-fn execute_group(group: CommandGroup) -> std::io::Result<std::process::Child> {
-    if let Some(first) = group.first() {
-        let mut cmd = Command::new(&first.name);
-        cmd.args(&first.args);
+// The pipe ends of a stage. The input is the output of the previous stage and the output goes to the next stage,
+// the first and the last stage use the interpreter's own input and output
+struct StagePipes {
+    input: Option<PipeReader>,
+    output: Option<PipeWriter>,
+    // the read end of the output pipe. A copy of the interpreter must close it, otherwise its output would stay
+    // readable after the next stage exits and the copy would never get a SIGPIPE
+    output_reader: Option<RawFd>
+}
+
+impl StagePipes {
+    fn none() -> Self {
+        StagePipes { input: None, output: None, output_reader: None }
+    }
+}
+
+// Spawn the process of a single command, reading from and writing to the pipes of its stage.
+// With job control the process is put into the process group pgid (0 starts a new group) and a foreground process takes the terminal.
+// The process gets the exported variables and the command's own assignments as its environment.
+// Returns the pid, or None if there was nothing to run (a command with only assignments and redirections)
+fn spawn_command(shell: &mut Shell, command: &SingleCommand, expanded: &ExpandedCommand, pipes: StagePipes, pgid: libc::pid_t, foreground: bool) -> io::Result<Option<libc::pid_t>> {
+    // a missing file of a redirection is not a missing command
+    let redirections = redirection::prepare(shell, &command.redirections).map_err(io::Error::other)?;
+    let Some((name, args)) = expanded.words.split_first() else {
+        return Ok(None);
+    };
 
-        if let Some(in_red) = &first.in_redirect {
-            let file = File::open(in_red)?;
-            cmd.stdin(file);
+    let mut proc = process::Command::new(name);
+    proc.args(args);
+    proc.env_clear();
+    proc.envs(shell.variables.environment());
+    proc.envs(expanded.assignments.iter().map(|(name, value)| (name, value)));
+    // the pipes are set up first, the redirections are applied on top of them
+    if let Some(input) = pipes.input {
+        proc.stdin(Stdio::from(input));
+    }
+    if let Some(output) = pipes.output {
+        proc.stdout(Stdio::from(output));
+    }
+
+    let mut terminal_fd = None;
+    if let Some(terminal) = &shell.terminal {
+        proc.process_group(pgid);
+        if foreground {
+            terminal_fd = Some(terminal.fd());
         }
+    }
+    let actions = redirections.actions.clone();
+    unsafe {
+        proc.pre_exec(move || {
+            // the interpreter gives the terminal to the group too, but the program might try to use it before that
+            if let Some(fd) = terminal_fd {
+                libc::tcsetpgrp(fd, libc::getpgrp());
+            }
+            redirection::apply_in_child(&actions)?;
+            signals::reset_to_default();
+            Ok(())
+        });
+    }
 
-        if let Some(out_red) = &first.out_redirect {
-            let file = File::create(out_red)?;
-            cmd.stdout(file);
+    let result = proc.spawn();
+    drop(redirections);
+    match result {
+        Ok(child) => Ok(Some(child.id() as libc::pid_t)),
+        // a name without a '/' was looked up in PATH
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !name.contains('/') => Err(io::Error::new(e.kind(), format!("{}: command not found", name))),
+        Err(e) => Err(io::Error::new(e.kind(), format!("{}: {}", name, builtin_commands::error_message(&e))))
+    }
+}
+
+// Start all the stages of a pipeline, each one reading the output of the previous one. The processes form a job.
+// A stage that fails to start is reported and the rest of the pipeline is still run, the next stage then reads an empty pipe.
+// If the last stage didn't start a process, its exit status is returned with the job
+fn spawn_pipeline(shell: &mut Shell, stages: &[Stage], command: String, foreground: bool) -> (Option<Job>, Option<i32>) {
+    let mut pids: Vec<libc::pid_t> = Vec::new();
+    let mut pgid = 0;
+    let mut final_status = None;
+    let mut input: Option<PipeReader> = None;
+    for (i, stage) in stages.iter().enumerate() {
+        let (next_input, output) = if i + 1 == stages.len() {
+            (None, None)
         } else {
-            cmd.stdout(Stdio::piped());
+            match io::pipe() {
+                Ok((reader, writer)) => (Some(reader), Some(writer)),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    final_status = Some(1);
+                    break;
+                }
+            }
+        };
+        let pipes = StagePipes { input: input.take(), output, output_reader: next_input.as_ref().map(|r| r.as_raw_fd()) };
+        let result = match stage {
+            Stage::Simple(_, Err(e)) => Err(io::Error::other(e.clone())),
+            Stage::Simple(single, Ok(expanded)) if !stage.runs_in_shell(shell) => spawn_command(shell, single, expanded, pipes, pgid, foreground),
+            _ => {
+                let group = shell.terminal.is_some().then_some(pgid);
+                fork_shell(shell, group, foreground, pipes, |shell| run_in_current_shell(shell, stage).unwrap_or(0)).map(Some)
+            }
+        };
+        input = next_input;
+        final_status = match result {
+            Ok(Some(pid)) => {
+                if pgid == 0 {
+                    pgid = if shell.terminal.is_some() { pid } else { unsafe { libc::getpgrp() } };
+                }
+                pids.push(pid);
+                None
+            },
+            Ok(None) => Some(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                Some(if e.kind() == io::ErrorKind::NotFound { COMMAND_NOT_FOUND } else { 1 })
+            }
+        };
+    }
+    if pids.is_empty() {
+        return (None, final_status);
+    }
+    (Some(Job::make(pgid, pids, command)), final_status)
+}
+
+// Set the shell variables of a command that consists only of assignments (and redirections, which are performed
+// for their side effects, e.g creating the files). The exit status is the status of the last command substitution
+fn assign_variables(shell: &mut Shell, command: &SingleCommand, expanded: &ExpandedCommand) -> i32 {
+    if let Err(e) = redirection::prepare(shell, &command.redirections) {
+        eprintln!("{}", e);
+        return 1;
+    }
+    for (name, value) in &expanded.assignments {
+        if let Err(e) = shell.variables.set(name, value) {
+            eprintln!("{}", e);
+            return 1;
+        }
+    }
+    shell.substitution_status.unwrap_or(0)
+}
+
+// Run a builtin command. The assignments before its name only last while it runs
+fn run_builtin(shell: &mut Shell, builtin: &dyn Builtin, command: &SingleCommand, expanded: &ExpandedCommand) -> i32 {
+    let mut saved_variables = Vec::new();
+    for (name, value) in &expanded.assignments {
+        let previous = shell.variables.get(name).map(String::from);
+        if let Err(e) = shell.variables.set(name, value) {
+            eprintln!("{}", e);
+            return 1;
+        }
+        saved_variables.push((name, previous));
+    }
+    // the builtin runs in the interpreter, so the redirections are applied to the interpreter's own descriptors for a while
+    let status = match redirection::prepare(shell, &command.redirections).and_then(|r| redirection::apply_in_shell(&r.actions)) {
+        Ok(saved_fds) => {
+            let (mut stdin, mut stdout, mut stderr) = (io::stdin(), io::stdout(), io::stderr());
+            let mut streams = BuiltinIo { stdin: &mut stdin, stdout: &mut stdout, stderr: &mut stderr };
+            let status = builtin.run(shell, &expanded.words, &mut streams).unwrap_or_else(|e| {
+                eprintln!("{}: {}", builtin.name(), e);
+                1
+            });
+            // the output still in the buffer belongs to the redirected descriptors
+            let _ = io::stdout().flush();
+            saved_fds.restore();
+            status
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    };
+    for (name, previous) in saved_variables.into_iter().rev() {
+        let _ = match previous {
+            Some(value) => shell.variables.set(name, &value),
+            None => shell.variables.unset(name)
+        };
+    }
+    status
+}
+
+// Evaluate an arithmetic expression after expanding it like a word in double quotes
+fn evaluate_arithmetic(shell: &mut Shell, expression: &str) -> Result<i64, String> {
+    let expression = expand_word(shell, expression)?;
+    arithmetic::evaluate(shell, &expression)
+}
+
+// Run ((expression)). The status is 0 if the value of the expression is not zero
+fn run_arithmetic(shell: &mut Shell, expression: &str) -> i32 {
+    match evaluate_arithmetic(shell, expression) {
+        Ok(value) => (value == 0) as i32,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Run [[ expression ]]. The status is 0 if the expression is true, 1 if it is false and 2 if it couldn't be evaluated
+fn run_conditional(shell: &mut Shell, expression: &str) -> i32 {
+    match conditional::evaluate(shell, expression) {
+        Ok(value) => (!value) as i32,
+        Err(e) => {
+            eprintln!("[[: {}", e);
+            2
+        }
+    }
+}
+
+// Run the if command: the body of the first branch whose condition succeeds, otherwise the else body.
+// The status is 0 if no body was run
+fn run_if(shell: &mut Shell, command: &IfCommand) -> i32 {
+    for (condition, body) in &command.branches {
+        let status = execute_list(shell, condition);
+        if shell.skips_rest() {
+            return status;
+        }
+        if status == 0 {
+            return execute_list(shell, body);
+        }
+    }
+    match &command.else_body {
+        Some(body) => execute_list(shell, body),
+        None => 0
+    }
+}
+
+// Decide whether a loop goes on after its condition or its body ran, taking care of a break or continue meant for it
+fn loop_goes_on(shell: &mut Shell) -> bool {
+    match shell.control_flow {
+        Some(ControlFlow::Break(levels)) => {
+            shell.control_flow = (levels > 1).then_some(ControlFlow::Break(levels - 1));
+            false
+        },
+        Some(ControlFlow::Continue(levels)) if levels > 1 => {
+            shell.control_flow = Some(ControlFlow::Continue(levels - 1));
+            false
+        },
+        Some(ControlFlow::Continue(_)) => {
+            shell.control_flow = None;
+            true
+        },
+        Some(ControlFlow::Return) => false,
+        None => {
+            shell.poll_interrupt();
+            !shell.skips_rest()
+        }
+    }
+}
+
+// Run the body of a while loop as long as the condition succeeds, of an until loop as long as it fails.
+// The status is the status of the last run of the body, 0 if it never ran
+fn run_loop(shell: &mut Shell, command: &LoopCommand) -> i32 {
+    let mut status = 0;
+    shell.loop_depth += 1;
+    loop {
+        let condition = execute_list(shell, &command.condition);
+        if !loop_goes_on(shell) || (condition == 0) == command.until {
+            break;
+        }
+        status = execute_list(shell, &command.body);
+        if !loop_goes_on(shell) {
+            break;
+        }
+    }
+    shell.loop_depth -= 1;
+    status
+}
+
+// Run the body of a for loop once for each field of the expanded words, with the variable set to the field
+fn run_for(shell: &mut Shell, command: &ForCommand) -> i32 {
+    // without words the loop goes through the positional parameters
+    let Some(words) = &command.words else {
+        return run_for_values(shell, command, shell.positional.clone());
+    };
+    let mut values = Vec::new();
+    for word in words {
+        match expand_word_to_fields(shell, word) {
+            Ok(fields) => values.extend(fields),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    }
+    run_for_values(shell, command, values)
+}
+
+fn run_for_values(shell: &mut Shell, command: &ForCommand, values: Vec<String>) -> i32 {
+    let mut status = 0;
+    shell.loop_depth += 1;
+    for value in values {
+        if let Err(e) = shell.variables.set(&command.name, &value) {
+            eprintln!("{}", e);
+            status = 1;
+            break;
+        }
+        status = execute_list(shell, &command.body);
+        if !loop_goes_on(shell) {
+            break;
+        }
+    }
+    shell.loop_depth -= 1;
+    status
+}
+
+// Run for ((init; condition; step)): evaluate init, then run the body and evaluate step while the condition is not zero
+fn run_arithmetic_for(shell: &mut Shell, command: &ArithmeticForCommand) -> i32 {
+    // an empty expression does nothing, an empty condition is true
+    let evaluate = |shell: &mut Shell, expression: &str| {
+        if expression.is_empty() { Ok(1) } else { evaluate_arithmetic(shell, expression) }
+    };
+    if let Err(e) = evaluate(shell, &command.init) {
+        eprintln!("{}", e);
+        return 1;
+    }
+    let mut status = 0;
+    shell.loop_depth += 1;
+    loop {
+        match evaluate(shell, &command.condition) {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("{}", e);
+                status = 1;
+                break;
+            }
+        }
+        status = execute_list(shell, &command.body);
+        if !loop_goes_on(shell) {
+            break;
+        }
+        if let Err(e) = evaluate(shell, &command.step) {
+            eprintln!("{}", e);
+            status = 1;
+            break;
+        }
+    }
+    shell.loop_depth -= 1;
+    status
+}
+
+// Run the body of the first case item with a pattern that matches the word. The status is 0 if no pattern matches
+fn run_case(shell: &mut Shell, command: &CaseCommand) -> i32 {
+    let word = match expand_word(shell, &command.word) {
+        Ok(word) => word,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    for item in &command.items {
+        for pattern in &item.patterns {
+            match expand_pattern(shell, pattern) {
+                Ok(pattern) if Pattern::make(&pattern).matches(&word) => return execute_list(shell, &item.body),
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 1;
+                }
+            }
+        }
+    }
+    0
+}
+
+// Run a compound command. Its redirections are applied to the interpreter's own descriptors while it runs, the commands
+// inside inherit them
+fn run_compound(shell: &mut Shell, command: &CompoundCommand, redirections: &[Redirection]) -> i32 {
+    let saved_fds = match redirection::prepare(shell, redirections).and_then(|r| redirection::apply_in_shell(&r.actions)) {
+        Ok(saved_fds) => saved_fds,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
         }
+    };
+    let status = match command {
+        CompoundCommand::Arithmetic(expression) => run_arithmetic(shell, expression),
+        CompoundCommand::Conditional(expression) => run_conditional(shell, expression),
+        CompoundCommand::If(command) => run_if(shell, command),
+        CompoundCommand::Loop(command) => run_loop(shell, command),
+        CompoundCommand::For(command) => run_for(shell, command),
+        CompoundCommand::ArithmeticFor(command) => run_arithmetic_for(shell, command),
+        CompoundCommand::Case(command) => run_case(shell, command),
+        // the interpreter forks before it runs a subshell, see Stage::runs_in_shell
+        CompoundCommand::Subshell(list) | CompoundCommand::BraceGroup(list) => execute_list(shell, list)
+    };
+    let _ = io::stdout().flush();
+    saved_fds.restore();
+    status
+}
+
+// Call a function: the arguments become the positional parameters and the assignments before the name last until
+// it returns, like its local variables. The redirections apply to the whole body
+fn run_function(shell: &mut Shell, body: &CommandList, command: &SingleCommand, expanded: &ExpandedCommand) -> i32 {
+    let name = &expanded.words[0];
+    if shell.function_frames.len() >= MAX_FUNCTION_DEPTH {
+        eprintln!("{}: maximum function nesting level exceeded ({})", name, MAX_FUNCTION_DEPTH);
+        return 1;
+    }
+    let saved_fds = match redirection::prepare(shell, &command.redirections).and_then(|r| redirection::apply_in_shell(&r.actions)) {
+        Ok(saved_fds) => saved_fds,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let mut frame = FunctionFrame { saved_variables: Vec::new() };
+    for (name, value) in &expanded.assignments {
+        frame.saved_variables.push((name.clone(), shell.variables.save(name)));
+        if let Err(e) = shell.variables.set(name, value) {
+            eprintln!("{}", e);
+        }
+    }
+    shell.function_frames.push(frame);
+    let positional = std::mem::replace(&mut shell.positional, expanded.words[1..].to_vec());
+    // the loops around the call can't be left with break or continue from inside the function
+    let loop_depth = std::mem::replace(&mut shell.loop_depth, 0);
 
-        let mut proc = cmd.spawn()?;
+    let mut status = execute_list(shell, body);
+    if shell.control_flow == Some(ControlFlow::Return) {
+        shell.control_flow = None;
+        status = shell.last_status;
+    }
 
-        for cmd in group.x_to_last(1) {
-            proc = advance_pipe_sequence(proc, cmd)?;
+    shell.loop_depth = loop_depth;
+    shell.positional = positional;
+    if let Some(frame) = shell.function_frames.pop() {
+        for (name, saved) in frame.saved_variables.into_iter().rev() {
+            shell.variables.restore(&name, saved);
         }
-        return Ok(proc);
     }
-    Err(Error::new(ErrorKind::Other, "Nothing to do"))
+    let _ = io::stdout().flush();
+    saved_fds.restore();
+    status
+}
+
+// Run the stage in the interpreter itself if it is a builtin command, a command with only assignments or a compound
+// command. Returns the exit status if it was run
+fn run_in_current_shell(shell: &mut Shell, stage: &Stage) -> Option<i32> {
+    match stage {
+        Stage::Simple(_, Err(e)) => {
+            eprintln!("{}", e);
+            Some(1)
+        },
+        Stage::Simple(single, Ok(expanded)) => match expanded.words.first() {
+            None => Some(assign_variables(shell, single, expanded)),
+            Some(name) => match expanded.function(shell) {
+                Some(body) => Some(run_function(shell, &body, single, expanded)),
+                None => builtin_commands::find(name).map(|builtin| run_builtin(shell, builtin, single, expanded))
+            }
+        },
+        Stage::Compound(Command::FunctionDefinition(function)) => {
+            shell.functions.insert(function.name.clone(), Rc::clone(&function.body));
+            Some(0)
+        },
+        Stage::Compound(Command::Compound(command, redirections)) => Some(run_compound(shell, command, redirections)),
+        Stage::Compound(Command::Simple(_)) => None
+    }
+}
+
+// Run the pipeline and store its exit status as the last status ($?)
+fn run_pipeline(shell: &mut Shell, pipeline: &Pipeline) -> i32 {
+    let stages = prepare_stages(shell, pipeline);
+    // Ctrl-C ended a command substitution, the pipeline isn't started. The status is already set
+    if shell.interrupted {
+        return shell.last_status;
+    }
+    let status = match stages.as_slice() {
+        [stage] if stage.runs_in_shell(shell) => run_in_current_shell(shell, stage),
+        _ => None
+    };
+    let status = match status {
+        Some(status) => status,
+        None => {
+            let (job, final_status) = spawn_pipeline(shell, &stages, pipeline.to_string(), true);
+            let status = match job {
+                Some(job) => jobs::run_in_foreground(shell, job, false),
+                None => 0
+            };
+            if let Some(terminal) = &shell.terminal {
+                // a program that failed to start might have taken the terminal already
+                let _ = terminal.take_back();
+                let _ = terminal.restore_original();
+            }
+            match final_status {
+                Some(s) if status != jobs::STOPPED_STATUS => s,
+                _ => status
+            }
+        }
+    };
+    let status = if pipeline.negated { (status == 0) as i32 } else { status };
+    shell.last_status = status;
+    status
 }
 
-pub fn execute_commands(commands: &mut Commands) {
-    let mut processes: Vec<std::process::Child> = Vec::new();
+// Run the pipelines of the chain. A pipeline after '&&' runs only if the previous status was zero, after '||' only if it was not
+fn run_and_or(shell: &mut Shell, and_or: &AndOr) -> i32 {
+    let mut status = run_pipeline(shell, &and_or.first);
+    for (operator, pipeline) in &and_or.rest {
+        if shell.skips_rest() {
+            break;
+        }
+        let run_next = match operator {
+            AndOrOperator::And => status == 0,
+            AndOrOperator::Or => status != 0
+        };
+        if run_next {
+            status = run_pipeline(shell, pipeline);
+        }
+    }
+    status
+}
 
-    while commands.has_more() {
-        if let Some(group) = commands.get_last() {
-            if let Ok(proc) = execute_group(group) {
-                processes.push(proc);
+// Run the function in a copy of the shell process, which exits with the status the function returns. The copy joins
+// the process group pgid if one is given (0 starts a new group) and takes the terminal if it runs in the foreground.
+// It reads from and writes to the pipes if there are some
+fn fork_shell(shell: &mut Shell, pgid: Option<libc::pid_t>, foreground: bool, pipes: StagePipes, run: impl FnOnce(&mut Shell) -> i32) -> io::Result<libc::pid_t> {
+    // anything left in the output buffer would be printed twice
+    io::stdout().flush()?;
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            if let Some(pgid) = pgid {
+                unsafe { libc::setpgid(0, pgid) };
+                if let Some(terminal) = shell.terminal.as_ref().filter(|_| foreground) {
+                    unsafe { libc::tcsetpgrp(terminal.fd(), libc::getpgrp()) };
+                }
+            }
+            if let Some(fd) = pipes.output_reader {
+                unsafe { libc::close(fd) };
+            }
+            if let Some(input) = &pipes.input {
+                unsafe { libc::dup2(input.as_raw_fd(), libc::STDIN_FILENO) };
             }
+            if let Some(output) = &pipes.output {
+                unsafe { libc::dup2(output.as_raw_fd(), libc::STDOUT_FILENO) };
+            }
+            drop(pipes);
+            signals::reset_to_default();
+            // the copy has no job control of its own and must not touch the terminal under the interpreter
+            shell.terminal = None;
+            shell.jobs = JobTable::make();
+            let status = run(shell);
+            let _ = io::stdout().flush();
+            unsafe { libc::_exit(status) }
+        },
+        pid => {
+            if let Some(pgid) = pgid {
+                unsafe { libc::setpgid(pid, if pgid == 0 { pid } else { pgid }) };
+            }
+            Ok(pid)
         }
     }
+}
+
+// Run the function in a copy of the shell process so that it runs in parallel with the rest. The copy leads its own
+// process group
+fn spawn_shell_copy(shell: &mut Shell, command: String, run: impl FnOnce(&mut Shell) -> i32) -> io::Result<Job> {
+    let pid = fork_shell(shell, Some(0), false, StagePipes::none(), run)?;
+    Ok(Job::make(pid, vec![pid], command))
+}
 
-    // gather the outputs
-    for p in processes {
-        if let Ok(out) = p.wait_with_output() {
-            if let Ok(result) = std::str::from_utf8(&out.stdout) {
-                print!("{}", result);
+// Run the commands of a command substitution in a copy of the shell and collect their output. The copy stays in the
+// interpreter's process group, so Ctrl-C ends it. The exit status of the commands becomes the last status
+pub fn run_substitution(shell: &mut Shell, text: &str) -> Result<String, String> {
+    let list = tokenizer::tokenize(text).and_then(parser::parse).map_err(|e| e.to_string())?;
+    let (mut reader, writer) = io::pipe().map_err(|e| e.to_string())?;
+    // anything left in the output buffer would be printed twice
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let result = match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().to_string()),
+        0 => {
+            drop(reader);
+            unsafe { libc::dup2(writer.as_raw_fd(), libc::STDOUT_FILENO) };
+            drop(writer);
+            signals::default_interrupt_signals();
+            shell.terminal = None;
+            shell.jobs = JobTable::make();
+            let status = execute_list(shell, &list);
+            let _ = io::stdout().flush();
+            unsafe { libc::_exit(status) }
+        },
+        pid => {
+            drop(writer);
+            let mut output = Vec::new();
+            let read_result = reader.read_to_end(&mut output);
+            let (status, signal) = jobs::wait_for_exit(pid);
+            // Ctrl-C ends the commands around the substitution too, like a foreground job. The terminal echoed ^C
+            if signal == Some(libc::SIGINT) && shell.terminal.is_some() {
+                println!();
+                shell.interrupted = true;
             }
+            shell.last_status = status;
+            shell.substitution_status = Some(status);
+            read_result.map(|_| String::from_utf8_lossy(&output).into_owned()).map_err(|e| e.to_string())
         }
+    };
+    // the commands might have changed the terminal settings
+    if let Some(terminal) = &shell.terminal {
+        let _ = terminal.restore_original();
     }
+    result
 }
 
+// Start the chain as a background job. A single pipeline is started directly, anything else (a chain of pipelines,
+// a builtin or assignments) runs in a copy of the interpreter
+fn run_in_background(shell: &mut Shell, and_or: &AndOr) {
+    let result = if and_or.rest.is_empty() {
+        let pipeline = &and_or.first;
+        let stages = prepare_stages(shell, pipeline);
+        match stages.as_slice() {
+            [stage] if stage.runs_in_shell(shell) => spawn_shell_copy(shell, pipeline.to_string(), |shell| run_in_current_shell(shell, stage).unwrap_or(0)),
+            _ => {
+                let (job, _) = spawn_pipeline(shell, &stages, pipeline.to_string(), false);
+                if let Some(job) = job {
+                    jobs::run_in_background(shell, job);
+                }
+                return;
+            }
+        }
+    } else {
+        spawn_shell_copy(shell, and_or.to_string(), |shell| run_and_or(shell, and_or))
+    };
+    match result {
+        Ok(job) => jobs::run_in_background(shell, job),
+        Err(e) => eprintln!("{}", e)
+    }
+}
+
+// Run all the and-or chains of the list. The ones followed by a '&' are started as background jobs.
+// Returns the exit status of the last chain
+pub fn execute_list(shell: &mut Shell, list: &CommandList) -> i32 {
+    let mut status = 0;
+    for item in &list.items {
+        shell.poll_interrupt();
+        if shell.skips_rest() {
+            break;
+        }
+        if item.background {
+            run_in_background(shell, &item.and_or);
+            shell.last_status = 0;
+            status = 0;
+        } else {
+            status = run_and_or(shell, &item.and_or);
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::tokenizer::tokenize;
+
+    fn run_in(shell: &mut Shell, input: &str) -> i32 {
+        let list = parse(tokenize(input).unwrap()).unwrap();
+        execute_list(shell, &list)
+    }
+
+    fn run(input: &str) -> i32 {
+        run_in(&mut Shell::make(None), input)
+    }
+
+    #[test]
+    fn exit_statuses_drive_and_or_chains() {
+        assert_eq!(run("true && false"), 1);
+        assert_eq!(run("false || true"), 0);
+        assert_eq!(run("false && true"), 1);
+        assert_eq!(run("false; true"), 0);
+        assert_eq!(run("{ false; } || { true && false; }"), 1);
+        assert_eq!(run("no-such-command-here"), 127);
+        assert_eq!(run("./no-such-command-here | true; /no/such/path"), 127);
+    }
+
+    #[test]
+    fn last_status_is_kept() {
+        let mut shell = Shell::make(None);
+        run_in(&mut shell, "false");
+        assert_eq!(shell.last_status, 1);
+        assert_eq!(run_in(&mut shell, "sh -c 'exit $1' - $?"), 1);
+        assert_eq!(run("sh -c 'kill -TERM $$'"), 128 + 15);
+    }
+
+    #[test]
+    fn output_redirections() {
+        let path = std::env::temp_dir().join(format!("execution-test-{}", std::process::id()));
+        let target = path.to_str().unwrap();
+        run(&format!("echo one > {0}; echo two >> {0}; sh -c 'echo three >&2' 2>>{0}; sh -c 'echo four >&3' 3>>{0}", target));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\nthree\nfour\n");
+        assert_eq!(run(&format!("cat < {0} > /dev/null && cat < {0}.missing", target)), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn here_documents_and_strings() {
+        let path = std::env::temp_dir().join(format!("here-document-test-{}", std::process::id()));
+        let target = path.to_str().unwrap();
+        run(&format!("false; cat <<EOF > {0}; cat <<-'EOF' >> {0}; cat <<< \"a  b\" >> {0}\nstatus $?\nEOF\n\tlast $?\n\tEOF\n", target));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "status 1\nlast $?\na  b\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn variables_and_the_environment() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run_in(&mut shell, "A=3; sh -c 'exit ${A:-0}'"), 0);
+        assert_eq!(run_in(&mut shell, "export A; sh -c 'exit $A'"), 3);
+        assert_eq!(run_in(&mut shell, "B=4 sh -c 'exit $B'"), 4);
+        assert_eq!(shell.variables.get("B"), None);
+        assert_eq!(run_in(&mut shell, "readonly A; A=5"), 1);
+        assert_eq!(run_in(&mut shell, "unset A"), 1);
+        assert_eq!(run_in(&mut shell, "C='a  b'; sh -c 'exit $#' - $C \"$C\""), 3);
+    }
+
+    #[test]
+    fn command_substitution() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run_in(&mut shell, "A=$(echo a; echo b)x; B=\"$(printf 'x\\n\\n')\"; C=`echo c`$(sh -c 'exit 3')"), 3);
+        assert_eq!(shell.variables.get("A"), Some("a\nbx"));
+        assert_eq!(shell.variables.get("B"), Some("x"));
+        assert_eq!(shell.variables.get("C"), Some("c"));
+        assert_eq!(run_in(&mut shell, "sh -c 'exit $#' - $(echo 1 2 3) \"$(echo 1 2)\""), 4);
+        run_in(&mut shell, "D=$(case a in b|c) echo B;; a) echo A;; esac)-\"$(case x in (y) ;; ('x') (echo X);; esac)\"");
+        assert_eq!(shell.variables.get("D"), Some("A-X"));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run_in(&mut shell, "((i = 2 + 3)) && (( i - 5 ))"), 1);
+        assert_eq!(run_in(&mut shell, "let 'j = i * 2' k=j+1; sh -c 'exit $1' - $((k % 7))"), 4);
+        assert_eq!(run_in(&mut shell, "((1 / 0))"), 1);
+        assert_eq!(run_in(&mut shell, "((1)) | cat"), 0);
+    }
+
+    #[test]
+    fn builtins_in_pipelines_and_redirections() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run_in(&mut shell, "exit 3 | true"), 0);
+        assert!(!shell.exit_requested);
+        assert_eq!(run_in(&mut shell, "true | exit 3"), 3);
+        assert_eq!(run_in(&mut shell, "export V=1 | cat"), 0);
+        assert_eq!(shell.variables.get("V"), None);
+        let path = std::env::temp_dir().join(format!("builtin-redirection-test-{}", std::process::id()));
+        let target = path.to_str().unwrap();
+        assert_eq!(run_in(&mut shell, &format!("export W=2; export > {0}; dirs -x 2>> {0}", target)), 2);
+        let output = std::fs::read_to_string(&path).unwrap();
+        assert!(output.contains("export W=\"2\"\n") && output.ends_with("dirs: usage: dirs [-clpv] [+N] [-N]\n"));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(run_in(&mut shell, "exit 4"), 4);
+        assert!(shell.exit_requested);
+    }
+
+    #[test]
+    fn conditional_expressions() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run_in(&mut shell, "test -d / && [ a != b ] && [[ abc == a* && -n x ]]"), 0);
+        assert_eq!(run_in(&mut shell, "[ 1 -gt 2 ]"), 1);
+        assert_eq!(run_in(&mut shell, "[ a -eq 1 ]"), 2);
+        assert_eq!(run_in(&mut shell, "[ a"), 2);
+        assert_eq!(run_in(&mut shell, "V='a b'; [[ $V == 'a b' && $V =~ ^(a)' ' ]]"), 0);
+        assert_eq!(shell.variables.elements("BASH_REMATCH"), Some(vec![String::from("a "), String::from("a")]));
+    }
+
+    #[test]
+    fn control_flow() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run_in(&mut shell, "if false; then exit 1; elif true; then R=elif; else R=else; fi"), 0);
+        assert_eq!(shell.variables.get("R"), Some("elif"));
+        run_in(&mut shell, "i=0; s=; while ((i < 5)); do ((i++)); [ $i = 2 ] && continue; s=$s$i; [ $i = 4 ] && break; done");
+        assert_eq!(shell.variables.get("s"), Some("134"));
+        run_in(&mut shell, "s=; for a in 1 2; do for b in x y; do [ $b = y ] && continue 2; s=$s$a$b; done; done");
+        assert_eq!(shell.variables.get("s"), Some("1x2x"));
+        run_in(&mut shell, "s=; for ((i = 3; i; i--)) do until true; do s=never; done; s=$s$i; done");
+        assert_eq!(shell.variables.get("s"), Some("321"));
+        run_in(&mut shell, "V=b.txt; case $V in *.c) R=c;; a*|b*) R=ab;; *) R=other;; esac");
+        assert_eq!(shell.variables.get("R"), Some("ab"));
+        assert_eq!(run_in(&mut shell, "! true"), 1);
+        assert_eq!(run_in(&mut shell, "for a in 1; do false; done"), 1);
+        assert_eq!(run_in(&mut shell, "{ true; false; }"), 1);
+    }
+
+    #[test]
+    fn functions() {
+        let mut shell = Shell::make(None);
+        run_in(&mut shell, "f() { R=\"$# $1 $*\"; shift; S=\"$@\"; return 3; R=late; }");
+        assert_eq!(run_in(&mut shell, "f a 'b c' d"), 3);
+        assert_eq!((shell.variables.get("R"), shell.variables.get("S")), (Some("3 a a b c d"), Some("b c d")));
+        assert!(shell.positional.is_empty());
+        run_in(&mut shell, "V=outer; function g { local V=inner; W=$V; }; g");
+        assert_eq!((shell.variables.get("V"), shell.variables.get("W")), (Some("outer"), Some("inner")));
+        run_in(&mut shell, "count() { if [ $1 -gt 0 ]; then N=$N$1; count $(($1 - 1)); fi; }; N=; count 3");
+        assert_eq!(shell.variables.get("N"), Some("321"));
+        assert_eq!(run_in(&mut shell, "h() { for a in 1 2; do return $a; done; }; h; [ $? = 1 ]"), 0);
+        assert_eq!(shell.last_status, 0);
+        assert_eq!(run_in(&mut shell, "h"), 1);
+        assert_eq!(run_in(&mut shell, "return"), 1);
+    }
+
+    #[test]
+    fn recursion_stops_at_the_nesting_limit() {
+        // on a stack like the interpreter's, see main
+        let recursion = "n=0; h() { n=$((n + 1)); if true; then while true; do case x in x) { h; break; };; esac; done; fi; }; h";
+        let interpreter = std::thread::Builder::new().stack_size(crate::EXECUTION_STACK_SIZE).spawn(move || {
+            let mut shell = Shell::make(None);
+            run_in(&mut shell, recursion);
+            shell.variables.get("n").map(String::from)
+        });
+        assert_eq!(interpreter.unwrap().join().unwrap().as_deref(), Some(MAX_FUNCTION_DEPTH.to_string().as_str()));
+    }
+
+    #[test]
+    fn subshells_and_brace_groups() {
+        let mut shell = Shell::make(None);
+        assert_eq!(run_in(&mut shell, "V=1; (V=2; exit 5); [ $V = 1 ]"), 0);
+        assert_eq!(shell.last_status, 0);
+        assert_eq!(run_in(&mut shell, "(exit 5)"), 5);
+        assert!(!shell.exit_requested);
+        let path = std::env::temp_dir().join(format!("group-test-{}", std::process::id()));
+        let target = path.to_str().unwrap();
+        run_in(&mut shell, &format!("{{ echo b; echo a; }} | sort > {0}; (echo c; sh -c 'echo d >&2') 2>&1 >> {0} | cat >> {0}", target));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\nc\nd\n");
+        run_in(&mut shell, &format!("{{ W=set; }} > {}", target));
+        assert_eq!(shell.variables.get("W"), Some("set"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
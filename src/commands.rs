@@ -1,123 +1,331 @@
-// A command with name and arguments. Optionally redirected input and output files
-pub struct SingleCommand {
-    pub name: String,
-    pub args: Vec<String>,
-    pub in_redirect: Option<String>,
-    pub out_redirect: Option<String>
-}
+use std::fmt;
+use std::rc::Rc;
 
-impl SingleCommand {
-    pub fn make() -> Self {
-        SingleCommand { name: String::new(), args: Vec::new(), in_redirect: None, out_redirect: None }
-    }
+// The syntax tree of the input. The words are stored as they were written (quotes included),
+// they are expanded only when the command is about to be executed
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectionKind {
+    // <
+    Input,
+    // > and >|
+    Output,
+    // >>
+    Append,
+    // <>
+    ReadWrite,
+    // <& and >&. The target is a file descriptor number or '-' for closing the descriptor
+    DuplicateInput,
+    DuplicateOutput,
+    // << and <<-. The target is the delimiter, the lines are in the here-document
+    HereDoc,
+    // <<<. The target word is given as the input, followed by a newline
+    HereString
+}
 
-    pub fn is_uninitialized(&self) -> bool {
-        self.name.is_empty()
+impl RedirectionKind {
+    pub fn operator(&self) -> &'static str {
+        match self {
+            RedirectionKind::Input => "<",
+            RedirectionKind::Output => ">",
+            RedirectionKind::Append => ">>",
+            RedirectionKind::ReadWrite => "<>",
+            RedirectionKind::DuplicateInput => "<&",
+            RedirectionKind::DuplicateOutput => ">&",
+            RedirectionKind::HereDoc => "<<",
+            RedirectionKind::HereString => "<<<"
+        }
     }
 
-    // add a token to the name, arguments, or the input/output redirection files of the command
-    pub fn build(&mut self, token: &str, inp_redirection: bool, outp_redirection: bool) {
-        if self.name.is_empty() {
-            self.name = String::from(token);
-        } else if inp_redirection {
-            self.in_redirect = Some(String::from(token));
-        } else if outp_redirection {
-            self.out_redirect = Some(String::from(token));
-        } else {
-            self.args.push(String::from(token));
+    // The file descriptor redirected when the operator isn't preceded by a number
+    pub fn default_fd(&self) -> i32 {
+        match self {
+            RedirectionKind::Input | RedirectionKind::ReadWrite | RedirectionKind::DuplicateInput
+                | RedirectionKind::HereDoc | RedirectionKind::HereString => 0,
+            _ => 1
         }
     }
 }
 
-// A command group means a group of piped commands, i.e all commands separated by a pipe |
-// there is one command group for each '&' separated sequence
-pub struct CommandGroup {
-    commands: Vec<SingleCommand>
+// The lines of a here-document. If the delimiter was not quoted, the parameters in the lines are expanded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HereDocument {
+    pub body: String,
+    pub expand: bool
 }
 
-impl CommandGroup {
-    pub fn make() -> Self {
-        CommandGroup { commands: Vec::new() } 
-    }
+// A redirection of a file descriptor, e.g '< in.txt', '2>> log.txt' or '2>&1'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirection {
+    pub fd: i32,
+    pub kind: RedirectionKind,
+    pub target: String,
+    pub here_doc: Option<HereDocument>
+}
 
-    pub fn append(&mut self, cmd: SingleCommand) {
-        self.commands.push(cmd);
+// A variable assignment, NAME=value. The value is expanded when the command runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: String
+}
+
+// A command with name and arguments (the words) and the redirections in the order they were written.
+// The assignments before the name are given to the command's environment, without a name they set shell variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SingleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<String>,
+    pub redirections: Vec<Redirection>
+}
+
+impl SingleCommand {
+    pub fn make() -> Self {
+        SingleCommand { assignments: Vec::new(), words: Vec::new(), redirections: Vec::new() }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.commands.is_empty()
+        self.assignments.is_empty() && self.words.is_empty() && self.redirections.is_empty()
     }
+}
 
-    pub fn x_to_last(&self, x: usize) -> &[SingleCommand] {
-        &self.commands[x..] 
-    } 
+// if condition; then body; elif condition; then body; else body; fi
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfCommand {
+    // the condition and the body of the if and of each elif
+    pub branches: Vec<(CommandList, CommandList)>,
+    pub else_body: Option<CommandList>
+}
 
-    pub fn first(&self) -> Option<&SingleCommand> {
-        self.commands.first()
-    }
+// while condition; do body; done. An until loop runs the body while the condition fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopCommand {
+    pub until: bool,
+    pub condition: CommandList,
+    pub body: CommandList
+}
+
+// for name in words; do body; done. Without 'in words' the loop goes through the positional parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForCommand {
+    pub name: String,
+    pub words: Option<Vec<String>>,
+    pub body: CommandList
 }
 
-// The Commands struct holds a vector of command groups, i.e all command groups separated by a '&'
-pub struct Commands {
-    command_groups: Vec<CommandGroup>
+// for ((init; condition; step)); do body; done. An empty condition is true
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArithmeticForCommand {
+    pub init: String,
+    pub condition: String,
+    pub step: String,
+    pub body: CommandList
 }
 
-impl Commands {
-    pub fn make() -> Self {
-        Commands { command_groups: Vec::new() }
+// pattern | pattern) body ;;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: CommandList
+}
+
+// case word in items esac. The body of the first item with a pattern matching the word is run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseCommand {
+    pub word: String,
+    pub items: Vec<CaseItem>
+}
+
+// A command the interpreter runs itself, made of other commands or evaluating an expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompoundCommand {
+    // ((expression)), the status is 0 if the value of the expression is not zero
+    Arithmetic(String),
+    // [[ expression ]], the status is 0 if the conditional expression is true
+    Conditional(String),
+    If(IfCommand),
+    Loop(LoopCommand),
+    For(ForCommand),
+    ArithmeticFor(ArithmeticForCommand),
+    Case(CaseCommand),
+    // ( list ), run in a copy of the interpreter so that it can't change the variables or the directory of the interpreter
+    Subshell(CommandList),
+    // { list; }, run in the interpreter itself
+    BraceGroup(CommandList)
+}
+
+// name() { body; } or function name { body; }. Running it stores the function in the shell. The body is shared
+// with the stored function so that it outlives the input it was defined in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub body: Rc<CommandList>
+}
+
+// One stage of a pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Simple(SingleCommand),
+    // a compound command with the redirections written after it, e.g 'while ...; done < file'
+    Compound(CompoundCommand, Vec<Redirection>),
+    FunctionDefinition(FunctionDefinition)
+}
+
+// A group of piped commands, i.e all commands separated by a pipe |. A pipeline starting with '!' negates the exit status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AndOrOperator {
+    And,
+    Or
+}
+
+// Pipelines chained with '&&' and '||'. The pipeline after an operator is run depending on the exit status of the chain before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(AndOrOperator, Pipeline)>
+}
+
+// An and-or chain and the information about whether it should be run in the background (it was followed by a '&' instead of a ';')
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    pub background: bool
+}
+
+// The whole input: all the and-or chains in the order they were written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandList {
+    pub items: Vec<ListItem>
+}
+
+// The nodes are displayed as shell input, e.g when listing the jobs
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.fd == self.kind.default_fd() {
+            write!(f, "{} {}", self.kind.operator(), self.target)
+        } else {
+            write!(f, "{}{} {}", self.fd, self.kind.operator(), self.target)
+        }
     }
+}
 
-    pub fn append(&mut self, cmd_group: CommandGroup) {
-        self.command_groups.push(cmd_group);
+impl fmt::Display for SingleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.assignments.iter().map(|a| format!("{}={}", a.name, a.value))
+            .chain(self.words.iter().cloned())
+            .chain(self.redirections.iter().map(|r| r.to_string()))
+            .collect();
+        write!(f, "{}", parts.join(" "))
     }
+}
 
-    pub fn get_last(&mut self) -> Option<CommandGroup> {
-        self.command_groups.pop()
+impl fmt::Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundCommand::Arithmetic(expression) => write!(f, "(({}))", expression),
+            CompoundCommand::Conditional(expression) => write!(f, "[[ {} ]]", expression),
+            CompoundCommand::If(command) => {
+                for (i, (condition, body)) in command.branches.iter().enumerate() {
+                    write!(f, "{} {}; then {}; ", if i == 0 { "if" } else { "elif" }, condition, body)?;
+                }
+                if let Some(body) = &command.else_body {
+                    write!(f, "else {}; ", body)?;
+                }
+                write!(f, "fi")
+            },
+            CompoundCommand::Loop(command) => {
+                write!(f, "{} {}; do {}; done", if command.until { "until" } else { "while" }, command.condition, command.body)
+            },
+            CompoundCommand::For(command) => {
+                write!(f, "for {}", command.name)?;
+                if let Some(words) = &command.words {
+                    write!(f, " in {}", words.join(" "))?;
+                }
+                write!(f, "; do {}; done", command.body)
+            },
+            CompoundCommand::ArithmeticFor(command) => {
+                write!(f, "for (({}; {}; {})); do {}; done", command.init, command.condition, command.step, command.body)
+            },
+            CompoundCommand::Case(command) => {
+                write!(f, "case {} in ", command.word)?;
+                for item in &command.items {
+                    write!(f, "{}) {};; ", item.patterns.join(" | "), item.body)?;
+                }
+                write!(f, "esac")
+            },
+            CompoundCommand::Subshell(list) => write!(f, "({})", list),
+            CompoundCommand::BraceGroup(list) => {
+                // the '}' must follow a ';' unless the last command runs in the background
+                let separator = if list.items.last().is_some_and(|item| item.background) { "" } else { ";" };
+                write!(f, "{{ {}{} }}", list, separator)
+            }
+        }
     }
+}
 
-    pub fn has_more(&self) -> bool {
-        self.command_groups.len() > 0
+impl fmt::Display for FunctionDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}() {{ {}; }}", self.name, self.body)
     }
 }
 
-// Create the commands structure from the input tokens
-pub fn make_commands(tokens: Vec<&str>) -> Commands {
-    let mut commands: Commands = Commands::make(); 
-    let mut group: CommandGroup = CommandGroup::make();
-    let mut command: SingleCommand = SingleCommand::make();
-    let mut next_is_input_redirection = false;
-    let mut next_is_ouput_redirection = false;
-    for token in tokens {
-        if token.eq("&") {
-            // Add the command to the group. Add the group to the commands. Start building a new group
-            group.append(command);
-            command = SingleCommand::make();
-            commands.append(group);
-            group = CommandGroup::make();
-        } else if token.eq("|") {
-            // Add the command to the group. Start building a new command
-            group.append(command);
-            command = SingleCommand::make();
-        } else if token.eq("<") {
-            // the next token is the name of the file to read input from
-            next_is_input_redirection = true;
-        } else if token.eq(">") {
-            // the next token is the name of the file to write output to
-            next_is_ouput_redirection = true;
-        } else {
-            // build up the current command
-            command.build(token, next_is_input_redirection, next_is_ouput_redirection);
-            next_is_input_redirection = false;
-            next_is_ouput_redirection = false;
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Simple(single) => write!(f, "{}", single),
+            Command::Compound(compound, redirections) => {
+                write!(f, "{}", compound)?;
+                for redirection in redirections {
+                    write!(f, " {}", redirection)?;
+                }
+                Ok(())
+            },
+            Command::FunctionDefinition(function) => write!(f, "{}", function)
         }
     }
-    if !command.is_uninitialized() {
-        group.append(command);
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.commands.iter().map(|c| c.to_string()).collect();
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        write!(f, "{}", parts.join(" | "))
     }
+}
 
-    if !group.is_empty() {
-        commands.append(group);
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (operator, pipeline) in &self.rest {
+            match operator {
+                AndOrOperator::And => write!(f, " && {}", pipeline)?,
+                AndOrOperator::Or => write!(f, " || {}", pipeline)?
+            }
+        }
+        Ok(())
     }
+}
 
-    commands
-}
\ No newline at end of file
+// The items are separated with '; ' or with ' & ' after a background item
+impl fmt::Display for CommandList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                write!(f, "{}", if self.items[i - 1].background { " " } else { "; " })?;
+            }
+            write!(f, "{}", item.and_or)?;
+            if item.background {
+                write!(f, " &")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,538 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+use crate::arithmetic;
+use crate::expansion::{expand_pattern, expand_regex, expand_word};
+use crate::pattern::Pattern;
+use crate::shell::Shell;
+
+// The conditional expressions of the test and [ builtins and of the [[ ]] command: file tests, string and integer
+// comparisons, combined with '!', '-a' or '&&', '-o' or '||' and parentheses.
+// test gets its arguments expanded like any command. [[ ]] expands its words itself, without splitting them into fields
+// or globbing, only when they are needed: the right side of '==' and '!=' is a pattern, the right side of '=~'
+// an extended regular expression whose groups are stored in the array BASH_REMATCH
+
+// The operators that test a single operand, e.g '-f file'
+const UNARY_OPERATORS: [&str; 16] = ["-e", "-f", "-d", "-r", "-w", "-x", "-s", "-L", "-h", "-b", "-c", "-p", "-S", "-t", "-z", "-n"];
+
+// The operators between two operands, e.g 'a = b'. '=~' is only known to [[ ]]
+const BINARY_OPERATORS: [&str; 15] = ["=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef", "=~"];
+
+const INTEGER_OPERATORS: [&str; 6] = ["-eq", "-ne", "-lt", "-le", "-gt", "-ge"];
+
+fn is_unary_operator(word: &str) -> bool {
+    UNARY_OPERATORS.contains(&word)
+}
+
+fn is_binary_operator(word: &str, regex: bool) -> bool {
+    BINARY_OPERATORS.contains(&word) && (regex || word != "=~")
+}
+
+// Whether the calling process may read, write or execute (the mode) the file
+fn has_access(path: &str, mode: libc::c_int) -> bool {
+    CString::new(path).is_ok_and(|path| unsafe { libc::access(path.as_ptr(), mode) } == 0)
+}
+
+// Apply an operator of UNARY_OPERATORS to its operand
+fn unary_test(operator: &str, operand: &str) -> bool {
+    match operator {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-t" => operand.trim().parse::<i32>().is_ok_and(|fd| unsafe { libc::isatty(fd) } == 1),
+        "-L" | "-h" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        "-r" => has_access(operand, libc::R_OK),
+        "-w" => has_access(operand, libc::W_OK),
+        "-x" => has_access(operand, libc::X_OK),
+        _ => {
+            let Ok(metadata) = fs::metadata(operand) else {
+                return false;
+            };
+            let file_type = metadata.file_type();
+            match operator {
+                "-f" => file_type.is_file(),
+                "-d" => file_type.is_dir(),
+                "-s" => metadata.len() > 0,
+                "-b" => file_type.is_block_device(),
+                "-c" => file_type.is_char_device(),
+                "-p" => file_type.is_fifo(),
+                "-S" => file_type.is_socket(),
+                _ => true
+            }
+        }
+    }
+}
+
+fn compare_integers(left: i64, operator: &str, right: i64) -> bool {
+    match operator {
+        "-eq" => left == right,
+        "-ne" => left != right,
+        "-lt" => left < right,
+        "-le" => left <= right,
+        "-gt" => left > right,
+        _ => left >= right
+    }
+}
+
+// Compare two files or strings with a binary operator other than the integer comparisons, '==' with a string
+fn compare(left: &str, operator: &str, right: &str) -> bool {
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match operator {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        // a file that doesn't exist is older than any file that does
+        "-nt" => modified(left).is_some_and(|l| modified(right).is_none_or(|r| l > r)),
+        "-ot" => modified(right).is_some_and(|r| modified(left).is_none_or(|l| l < r)),
+        _ => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(l), Ok(r)) => l.dev() == r.dev() && l.ino() == r.ino(),
+            _ => false
+        }
+    }
+}
+
+// An integer operand of test
+fn parse_integer(text: &str) -> Result<i64, String> {
+    text.trim().parse::<i64>().map_err(|_| format!("{}: integer expression expected", text))
+}
+
+// A recursive descent parser and evaluator for the arguments of test
+//
+// expression : and ('-o' and)*
+// and        : not ('-a' not)*
+// not        : '!' not | primary
+// primary    : '(' expression ')' | WORD BINARY_OPERATOR WORD | UNARY_OPERATOR WORD | WORD
+//
+// A comparison is tried first, so '! = x' compares '!' with 'x' like the standard requires for three arguments
+struct TestParser<'a> {
+    args: &'a [String],
+    pos: usize
+}
+
+impl<'a> TestParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.pos).map(|arg| arg.as_str())
+    }
+
+    // whether a comparison starts at the current position
+    fn at_comparison(&self) -> bool {
+        self.pos + 2 < self.args.len() && is_binary_operator(&self.args[self.pos + 1], false)
+    }
+
+    fn parse_or(&mut self) -> Result<bool, String> {
+        let mut value = self.parse_and()?;
+        while self.peek() == Some("-o") {
+            self.pos += 1;
+            value |= self.parse_and()?;
+        }
+        Ok(value)
+    }
+
+    fn parse_and(&mut self) -> Result<bool, String> {
+        let mut value = self.parse_not()?;
+        while self.peek() == Some("-a") {
+            self.pos += 1;
+            value &= self.parse_not()?;
+        }
+        Ok(value)
+    }
+
+    fn parse_not(&mut self) -> Result<bool, String> {
+        if self.peek() == Some("!") && self.pos + 1 < self.args.len() && !self.at_comparison() {
+            self.pos += 1;
+            return Ok(!self.parse_not()?);
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<bool, String> {
+        let Some(word) = self.peek() else {
+            return Err(String::from("argument expected"));
+        };
+        if self.at_comparison() {
+            let (left, operator, right) = (word, &self.args[self.pos + 1], &self.args[self.pos + 2]);
+            self.pos += 3;
+            if INTEGER_OPERATORS.contains(&operator.as_str()) {
+                return Ok(compare_integers(parse_integer(left)?, operator, parse_integer(right)?));
+            }
+            return Ok(compare(left, operator, right));
+        }
+        if word == "(" && self.pos + 1 < self.args.len() {
+            self.pos += 1;
+            let value = self.parse_or()?;
+            if self.peek() != Some(")") {
+                return Err(String::from("')' expected"));
+            }
+            self.pos += 1;
+            return Ok(value);
+        }
+        if is_unary_operator(word) && self.pos + 1 < self.args.len() {
+            let operand = &self.args[self.pos + 1];
+            self.pos += 2;
+            return Ok(unary_test(word, operand));
+        }
+        self.pos += 1;
+        Ok(!word.is_empty())
+    }
+}
+
+// Evaluate the arguments of test (without the ']' of '['). No arguments are false, a single one is true if it is not empty
+pub fn test(args: &[String]) -> Result<bool, String> {
+    match args {
+        [] => return Ok(false),
+        // two arguments are a negation or a unary test
+        [first, _] if first != "!" && !is_unary_operator(first) => return Err(format!("{}: unary operator expected", first)),
+        _ => ()
+    }
+    let mut parser = TestParser { args, pos: 0 };
+    let value = parser.parse_or()?;
+    match parser.peek() {
+        Some(_) => Err(String::from("too many arguments")),
+        None => Ok(value)
+    }
+}
+
+// The syntax tree of a [[ ]] expression. The words are kept as they were written
+enum Expression {
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Unary(String, String),
+    Binary(String, String, String),
+    Word(String)
+}
+
+// The length of the text at the start of chars that belongs to a single word: a quoted string, an escaped character,
+// a parameter expansion or a command substitution. None for any other character
+fn quoted_length(chars: &[char]) -> Option<usize> {
+    let closing = |open: char, close: char, from: usize| {
+        let mut depth = 0;
+        for (i, c) in chars.iter().enumerate().skip(from) {
+            if *c == open {
+                depth += 1;
+            } else if *c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+        }
+        chars.len()
+    };
+    match chars {
+        ['\'', rest @ ..] => Some(rest.iter().position(|c| *c == '\'').map_or(chars.len(), |i| i + 2)),
+        ['"', ..] => {
+            let mut i = 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            Some((i + 1).min(chars.len()))
+        },
+        ['\\', _, ..] => Some(2),
+        ['$', '(', ..] => Some(closing('(', ')', 1)),
+        ['$', '{', ..] => Some(closing('{', '}', 1)),
+        ['`', rest @ ..] => Some(rest.iter().position(|c| *c == '`').map_or(chars.len(), |i| i + 2)),
+        _ => None
+    }
+}
+
+// Split the text of [[ ]] into its words. Unquoted '(', ')', '&&', '||', '<' and '>' are words of their own, except in
+// the regular expression after '=~', which ends only at whitespace outside parentheses
+fn split_words(text: &str) -> Vec<String> {
+    const OPERATORS: [&str; 6] = ["&&", "||", "(", ")", "<", ">"];
+    let chars: Vec<char> = text.chars().collect();
+    let operator_at = |pos: usize| OPERATORS.iter().find(|op| op.chars().enumerate().all(|(i, c)| chars.get(pos + i) == Some(&c)));
+    let mut words: Vec<String> = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        if chars[pos].is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        let regex = words.last().is_some_and(|word| word == "=~");
+        if let Some(op) = operator_at(pos).filter(|_| !regex) {
+            words.push(String::from(*op));
+            pos += op.len();
+            continue;
+        }
+        let mut word = String::new();
+        let mut depth = 0;
+        while pos < chars.len() {
+            let c = chars[pos];
+            if depth == 0 && (c.is_whitespace() || (!regex && operator_at(pos).is_some())) {
+                break;
+            }
+            let length = match quoted_length(&chars[pos..]) {
+                Some(length) => length,
+                None => {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => ()
+                    }
+                    1
+                }
+            };
+            word.extend(&chars[pos..pos + length]);
+            pos += length;
+        }
+        words.push(word);
+    }
+    words
+}
+
+// A recursive descent parser for the words of [[ ]]
+//
+// expression : and ('||' and)*
+// and        : not ('&&' not)*
+// not        : '!' not | primary
+// primary    : '(' expression ')' | WORD BINARY_OPERATOR WORD | UNARY_OPERATOR WORD | WORD
+struct ConditionalParser {
+    words: Vec<String>,
+    pos: usize
+}
+
+impl ConditionalParser {
+    fn peek(&self) -> Option<&str> {
+        self.words.get(self.pos).map(|word| word.as_str())
+    }
+
+    fn next_word(&mut self) -> Result<String, String> {
+        let word = self.words.get(self.pos).cloned().ok_or_else(|| String::from("unexpected end of the conditional expression"))?;
+        self.pos += 1;
+        Ok(word)
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_and()?;
+        while self.peek() == Some("||") {
+            self.pos += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_not()?;
+        while self.peek() == Some("&&") {
+            self.pos += 1;
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_not()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression, String> {
+        if self.peek() == Some("!") {
+            self.pos += 1;
+            return Ok(Expression::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        let word = self.next_word()?;
+        match word.as_str() {
+            "(" => {
+                let expression = self.parse_or()?;
+                if self.next_word()? != ")" {
+                    return Err(String::from("expected ')'"));
+                }
+                return Ok(expression);
+            },
+            ")" | "&&" | "||" => return Err(format!("unexpected '{}'", word)),
+            _ => ()
+        }
+        if let Some(operator) = self.peek().filter(|op| is_binary_operator(op, true)).map(String::from) {
+            self.pos += 1;
+            return Ok(Expression::Binary(word, operator, self.next_word()?));
+        }
+        if is_unary_operator(&word) {
+            return match self.peek() {
+                Some(next) if !matches!(next, "&&" | "||" | ")") => Ok(Expression::Unary(word, self.next_word()?)),
+                _ => Err(format!("argument expected after '{}'", word))
+            };
+        }
+        Ok(Expression::Word(word))
+    }
+}
+
+// A compiled POSIX extended regular expression
+struct Regex {
+    regex: Box<libc::regex_t>,
+    // the number of parenthesized groups
+    groups: usize
+}
+
+// The offset of re_nsub, the number of groups, in the C library's regex_t. The libc crate keeps the field private
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+const GROUP_COUNT_OFFSET: usize = 6 * std::mem::size_of::<usize>();
+#[cfg(all(target_os = "linux", target_env = "musl"))]
+const GROUP_COUNT_OFFSET: usize = 0;
+#[cfg(target_os = "macos")]
+const GROUP_COUNT_OFFSET: usize = std::mem::size_of::<usize>();
+
+impl Regex {
+    fn make(pattern: &str) -> Result<Self, String> {
+        let c_pattern = CString::new(pattern).map_err(|_| format!("{}: invalid regular expression", pattern))?;
+        let mut regex: Box<libc::regex_t> = Box::new(unsafe { std::mem::zeroed() });
+        let error = unsafe { libc::regcomp(&mut *regex, c_pattern.as_ptr(), libc::REG_EXTENDED) };
+        if error != 0 {
+            let mut message = [0u8; 256];
+            unsafe { libc::regerror(error, &*regex, message.as_mut_ptr() as *mut libc::c_char, message.len()) };
+            let length = message.iter().position(|b| *b == 0).unwrap_or(message.len());
+            return Err(format!("{}: {}", pattern, String::from_utf8_lossy(&message[..length])));
+        }
+        let groups = unsafe { (&*regex as *const libc::regex_t).cast::<u8>().add(GROUP_COUNT_OFFSET).cast::<libc::size_t>().read_unaligned() };
+        Ok(Regex { regex, groups })
+    }
+
+    // The text of the match and of each group if the expression matches a part of the text. A group that took no part
+    // in the match is empty
+    fn captures(&self, text: &str) -> Option<Vec<String>> {
+        let c_text = CString::new(text).ok()?;
+        let mut matches = vec![libc::regmatch_t { rm_so: -1, rm_eo: -1 }; self.groups + 1];
+        let result = unsafe { libc::regexec(&*self.regex, c_text.as_ptr(), matches.len(), matches.as_mut_ptr(), 0) };
+        if result != 0 {
+            return None;
+        }
+        let bytes = text.as_bytes();
+        Some(matches.iter().map(|m| match (usize::try_from(m.rm_so), usize::try_from(m.rm_eo)) {
+            (Ok(start), Ok(end)) => String::from_utf8_lossy(&bytes[start..end]).into_owned(),
+            _ => String::new()
+        }).collect())
+    }
+}
+
+impl Drop for Regex {
+    fn drop(&mut self) {
+        unsafe { libc::regfree(&mut *self.regex) };
+    }
+}
+
+// An integer operand of [[ ]], an arithmetic expression
+fn evaluate_integer(shell: &mut Shell, word: &str) -> Result<i64, String> {
+    let expression = expand_word(shell, word)?;
+    arithmetic::evaluate(shell, &expression)
+}
+
+fn evaluate_expression(shell: &mut Shell, expression: &Expression) -> Result<bool, String> {
+    Ok(match expression {
+        Expression::Not(inner) => !evaluate_expression(shell, inner)?,
+        Expression::And(left, right) => evaluate_expression(shell, left)? && evaluate_expression(shell, right)?,
+        Expression::Or(left, right) => evaluate_expression(shell, left)? || evaluate_expression(shell, right)?,
+        Expression::Word(word) => !expand_word(shell, word)?.is_empty(),
+        Expression::Unary(operator, word) => unary_test(operator, &expand_word(shell, word)?),
+        Expression::Binary(left, operator, right) => match operator.as_str() {
+            "=" | "==" | "!=" => {
+                let left = expand_word(shell, left)?;
+                let matches = Pattern::make(&expand_pattern(shell, right)?).matches(&left);
+                matches == (operator != "!=")
+            },
+            "=~" => {
+                let left = expand_word(shell, left)?;
+                let regex = Regex::make(&expand_regex(shell, right)?)?;
+                let captures = regex.captures(&left);
+                let matched = captures.is_some();
+                shell.variables.set_array("BASH_REMATCH", captures.unwrap_or_default())?;
+                matched
+            },
+            _ if INTEGER_OPERATORS.contains(&operator.as_str()) => {
+                let left = evaluate_integer(shell, left)?;
+                compare_integers(left, operator, evaluate_integer(shell, right)?)
+            },
+            _ => {
+                let left = expand_word(shell, left)?;
+                compare(&left, operator, &expand_word(shell, right)?)
+            }
+        }
+    })
+}
+
+fn parse(text: &str) -> Result<Expression, String> {
+    let mut parser = ConditionalParser { words: split_words(text), pos: 0 };
+    if parser.words.is_empty() {
+        return Err(String::from("empty conditional expression"));
+    }
+    let expression = parser.parse_or()?;
+    if let Some(word) = parser.peek() {
+        return Err(format!("unexpected '{}'", word));
+    }
+    Ok(expression)
+}
+
+// Check the text between '[[' and ']]' when the command is parsed, a malformed expression is a syntax error
+pub fn check_syntax(text: &str) -> Result<(), String> {
+    parse(text).map(|_| ())
+}
+
+// Evaluate the text between '[[' and ']]'
+pub fn evaluate(shell: &mut Shell, text: &str) -> Result<bool, String> {
+    let expression = parse(text)?;
+    evaluate_expression(shell, &expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_test(args: &str) -> Result<bool, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        test(&args)
+    }
+
+    #[test]
+    fn test_arguments() {
+        assert_eq!(run_test(""), Ok(false));
+        assert_eq!(run_test("x"), Ok(true));
+        assert_eq!(run_test("-n x"), Ok(true));
+        assert_eq!(run_test("-z x"), Ok(false));
+        assert_eq!(run_test("a = a"), Ok(true));
+        assert_eq!(run_test("a != a"), Ok(false));
+        assert_eq!(run_test("-n = -n"), Ok(true));
+        assert_eq!(run_test("1 -lt 2"), Ok(true));
+        assert_eq!(run_test("10 -le 9"), Ok(false));
+        assert_eq!(run_test("! -z x"), Ok(true));
+        assert_eq!(run_test("a = b -o 1 -eq 1"), Ok(true));
+        assert_eq!(run_test("a = a -a ( 1 -gt 2 )"), Ok(false));
+        assert_eq!(run_test("-d /"), Ok(true));
+        assert_eq!(run_test("-f /"), Ok(false));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(run_test("a -eq 1").is_err());
+        assert!(run_test("a b c").is_err());
+        assert!(run_test("( a").is_err());
+        assert_eq!(run_test("a ="), Err(String::from("a: unary operator expected")));
+        assert_eq!(run_test("-n ="), Ok(true));
+        assert_eq!(run_test("! ="), Ok(false));
+    }
+
+    #[test]
+    fn conditional_commands() {
+        let mut shell = Shell::make(None);
+        shell.variables.set("cond_words", "a b").unwrap();
+        let cases = [
+            ("$cond_words == 'a b'", true), ("-n $cond_unset", false), ("abc == a*", true), ("abc == 'a*'", false),
+            ("b < c && ! 2 -gt 10", true), ("-z x || ( 1 -lt 2 )", true), ("x =~ ^x$", true), ("xy =~ ^(x|z)+$", false)
+        ];
+        for (expression, value) in cases {
+            assert_eq!(evaluate(&mut shell, expression), Ok(value), "{}", expression);
+        }
+        assert!(evaluate(&mut shell, "").is_err());
+        assert!(evaluate(&mut shell, "a b").is_err());
+        assert_eq!(check_syntax("-f"), Err(String::from("argument expected after '-f'")));
+        assert!(check_syntax("-n x && ( -z )").is_err());
+        assert_eq!(check_syntax("-f = -f"), Ok(()));
+    }
+
+    #[test]
+    fn regex_groups_are_stored() {
+        let mut shell = Shell::make(None);
+        assert_eq!(evaluate(&mut shell, "key=value =~ ([a-z]+)=(.*)"), Ok(true));
+        assert_eq!(shell.variables.elements("BASH_REMATCH"), Some(vec![String::from("key=value"), String::from("key"), String::from("value")]));
+        assert_eq!(Regex::make("(a(b))\\(c\\)[(]").unwrap().groups, 2);
+        assert_eq!(Regex::make("x").unwrap().groups, 0);
+    }
+}
@@ -1,5 +1,7 @@
-use std::fs;
 use std::collections::HashMap;
+use std::path::Path;
+
+use crate::glob;
 
 // When the user types a tab (\t), we want to autocomplete the cursor's part of the input to the longest match in the working directory.
 // When the user changes the working directory, the prefix tree instance is updated. 
@@ -88,14 +90,8 @@ impl DirPrefixTree {
     // clear the old tree and add new entries from the current working directory.
     pub fn update_to_current_dir(&mut self) -> std::io::Result<()> {
         self.root = TreeNode::make();
-        let paths = fs::read_dir("./")?; 
-
-        for p in paths {
-            if let Ok(entry) = p {
-                if let Some(file_name) = entry.file_name().to_str() {
-                    self.insert(file_name);
-                }
-            }
+        for file_name in glob::directory_entries(Path::new("./"))? {
+            self.insert(&file_name);
         }
 
         Ok(())
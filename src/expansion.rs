@@ -0,0 +1,816 @@
+use std::ffi::{CStr, CString};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::arithmetic;
+use crate::braces;
+use crate::command_execution;
+use crate::glob;
+use crate::pattern::{self, Pattern};
+use crate::shell::Shell;
+use crate::tokenizer::CaseTracker;
+use crate::variables::is_valid_name;
+
+// The expansion of the words happens right before a command is run. After the brace expansion it replaces a leading '~'
+// with a home directory, the parameters ($?, $1, $@, $NAME, ${NAME},
+// ${NAME:-word} etc.) and the arithmetic expansions ($((...))) with their values and the command substitutions ($(...), `...`)
+// with the output of the commands,
+// splits the unquoted results into fields at the characters of IFS, replaces the fields with unquoted pattern characters
+// with the matching file names and removes the quotes
+
+// The field separators when IFS is not set
+const DEFAULT_IFS: &str = " \t\n";
+
+// The operators of ${NAME<operator>word}. Longer operators must come before their prefixes
+const PARAMETER_OPERATORS: [&str; 16] = [":-", ":=", ":?", ":+", "-", "=", "?", "+", "%%", "%", "##", "#", "//", "/#", "/%", "/"];
+
+// What the expanded word is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    // the arguments of a command, split into fields
+    Fields,
+    // a single string, e.g an assignment, a redirection target or a pattern
+    Text,
+    // the regular expression of '[[ text =~ regex ]]', whose pattern has the quoted characters escaped for a regular expression
+    Regex
+}
+
+// A field being built from a word
+#[derive(Default)]
+struct Field {
+    text: String,
+    // the same text as a pattern, with the quoted characters escaped
+    pattern: String,
+    // whether the field has unquoted pattern characters and is thus expanded to the matching file names
+    has_glob: bool,
+    // a quoted empty string ("" or '') still makes a field, an empty unquoted value doesn't
+    quoted: bool,
+    // set by "$@" without positional parameters (or an array without elements), which makes no field even though it is quoted
+    no_elements: bool
+}
+
+struct Expander<'a> {
+    shell: &'a mut Shell,
+    context: Context,
+    // the characters the unquoted values are split at
+    ifs: String,
+    fields: Vec<Field>,
+    current: Field,
+    // the last split was at whitespace, so a separator right after it doesn't start another field
+    after_whitespace: bool,
+    // set while expanding the word of an unquoted ${NAME:-word}, whose unquoted text is split like a value
+    in_unquoted_word: bool,
+    // in an assignment a '~' is expanded after every ':' too, e.g PATH=~/bin:~/.local/bin
+    tilde_after_colon: bool
+}
+
+impl<'a> Expander<'a> {
+    fn make(shell: &'a mut Shell, context: Context) -> Self {
+        let ifs = String::from(shell.variables.get("IFS").unwrap_or(DEFAULT_IFS));
+        Expander { shell, context, ifs, fields: Vec::new(), current: Field::default(), after_whitespace: false, in_unquoted_word: false, tilde_after_colon: false }
+    }
+
+    fn push(&mut self, c: char) {
+        self.current.text.push(c);
+        self.current.pattern.push(c);
+        self.current.has_glob |= matches!(c, '*' | '?' | '[');
+        self.after_whitespace = false;
+    }
+
+    fn push_quoted(&mut self, c: char) {
+        self.current.text.push(c);
+        if self.context == Context::Regex {
+            if "\\.[]()*+?{}|^$".contains(c) {
+                self.current.pattern.push('\\');
+            }
+            self.current.pattern.push(c);
+        } else {
+            pattern::escape_char(c, &mut self.current.pattern);
+        }
+        self.after_whitespace = false;
+    }
+
+    fn finish_field(&mut self) {
+        self.fields.push(std::mem::take(&mut self.current));
+    }
+
+    // Add the unquoted value of an expansion, splitting it into fields. A run of whitespace separators ends the field
+    // once, every other separator ends a field even if it is empty
+    fn push_unquoted(&mut self, value: &str) {
+        if self.context != Context::Fields {
+            value.chars().for_each(|c| self.push(c));
+            return;
+        }
+        let ifs = self.ifs.clone();
+        for c in value.chars() {
+            if !ifs.contains(c) {
+                self.push(c);
+            } else if c.is_whitespace() {
+                if !self.current.text.is_empty() || self.current.quoted {
+                    self.finish_field();
+                }
+                self.after_whitespace = true;
+            } else {
+                if !self.after_whitespace || !self.current.text.is_empty() || self.current.quoted {
+                    self.finish_field();
+                }
+                self.after_whitespace = false;
+            }
+        }
+    }
+
+    fn push_value(&mut self, value: &str, quoted: bool) {
+        if quoted {
+            value.chars().for_each(|c| self.push_quoted(c));
+        } else {
+            self.push_unquoted(value);
+        }
+    }
+
+    // Expand the text of a word. in_double_quotes tells whether the text is inside double quotes already,
+    // e.g the word of "${NAME:-word}"
+    fn expand(&mut self, word: &str, mut in_double_quotes: bool) -> Result<(), String> {
+        let mut chars = word.chars().peekable();
+        let mut previous = None;
+        while let Some(c) = chars.next() {
+            let at_tilde_position = previous.is_none() || (self.tilde_after_colon && previous == Some(':'));
+            previous = Some(c);
+            match c {
+                '~' if !in_double_quotes && at_tilde_position => {
+                    if !self.expand_tilde(&mut chars) {
+                        self.push(c);
+                    }
+                },
+                '\'' if !in_double_quotes => {
+                    self.current.quoted = true;
+                    for q in chars.by_ref() {
+                        if q == '\'' {
+                            break;
+                        }
+                        self.push_quoted(q);
+                    }
+                },
+                '"' => {
+                    in_double_quotes = !in_double_quotes;
+                    self.current.quoted = true;
+                },
+                '\\' => match chars.peek() {
+                    // inside double quotes a backslash only escapes $, `, ", \ and a newline
+                    Some('$') | Some('`') | Some('"') | Some('\\') | Some('\n') => {
+                        let escaped = chars.next().unwrap();
+                        self.push_quoted(escaped);
+                    },
+                    Some(_) if !in_double_quotes => {
+                        let escaped = chars.next().unwrap();
+                        self.push_quoted(escaped);
+                    },
+                    _ => self.push_quoted(c)
+                },
+                '$' => {
+                    // a '$' that doesn't start an expansion is taken literally, unquoted it can still be the end anchor
+                    // of a regular expression
+                    if !self.expand_dollar(&mut chars, in_double_quotes)? {
+                        if in_double_quotes {
+                            self.push_quoted(c);
+                        } else {
+                            self.push(c);
+                        }
+                    }
+                },
+                '`' => {
+                    let command = read_backquoted(&mut chars)?;
+                    self.substitute(&command, in_double_quotes)?;
+                },
+                _ if in_double_quotes => self.push_quoted(c),
+                _ if self.in_unquoted_word => self.push_unquoted(c.encode_utf8(&mut [0; 4])),
+                _ => self.push(c)
+            }
+        }
+        Ok(())
+    }
+
+    // Replace the '~' that was just read, with the characters after it up to the next '/', with a directory:
+    // '~' is the home directory, '~user' the home directory of the user, '~+' the working directory and '~-' the previous one.
+    // Returns false if the characters don't name a directory, in which case the '~' is taken literally
+    fn expand_tilde(&mut self, chars: &mut Peekable<Chars>) -> bool {
+        let prefix: String = chars.clone().take_while(|c| *c != '/' && !(self.tilde_after_colon && *c == ':')).collect();
+        let directory = match prefix.as_str() {
+            "" => self.parameter("HOME").or_else(|| home_directory(None)),
+            "+" => Some(self.shell.working_dir()),
+            "-" => self.parameter("OLDPWD"),
+            user if user.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) => home_directory(Some(user)),
+            _ => None
+        };
+        let Some(directory) = directory else {
+            return false;
+        };
+        for _ in prefix.chars() {
+            chars.next();
+        }
+        directory.chars().for_each(|c| self.push_quoted(c));
+        true
+    }
+
+    // The value of a parameter, None if it is not set. $@ and $* are the positional parameters joined with spaces here,
+    // see push_elements for how they are really expanded
+    fn parameter(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.shell.last_status.to_string()),
+            "#" => Some(self.shell.positional.len().to_string()),
+            "@" | "*" => Some(self.shell.positional.join(" ")),
+            _ if name.chars().all(|c| c.is_ascii_digit()) => match name.parse::<usize>().ok()? {
+                // ${00} is $0 too
+                0 => std::env::args().next(),
+                index => self.shell.positional.get(index - 1).cloned()
+            },
+            _ => self.shell.variables.get(name).map(String::from)
+        }
+    }
+
+    // Expand the parameter that starts after a '$'. Returns false if the characters don't form a parameter, in which case
+    // the '$' is taken literally. Unset parameters expand to nothing
+    fn expand_dollar(&mut self, chars: &mut Peekable<Chars>, quoted: bool) -> Result<bool, String> {
+        match chars.peek() {
+            Some('@') | Some('*') => {
+                let separate = chars.next() == Some('@');
+                let positional = self.shell.positional.clone();
+                self.push_elements(&positional, separate, quoted);
+            },
+            // the special parameters and the positional parameters $0 to $9
+            Some(c) if matches!(c, '?' | '#') || c.is_ascii_digit() => {
+                let name = chars.next().unwrap().to_string();
+                let value = self.parameter(&name).unwrap_or_default();
+                self.push_value(&value, quoted);
+            },
+            Some('{') => {
+                chars.next();
+                let inside = read_braced(chars)?;
+                self.expand_braced(&inside, quoted)?;
+            },
+            Some('(') => {
+                chars.next();
+                let command = read_parenthesized(chars)?;
+                // $((...)) is an arithmetic expansion, the expression is expanded like a word in double quotes first
+                match command.strip_prefix('(').and_then(|c| c.strip_suffix(')')) {
+                    Some(expression) => {
+                        let expression = expand_word(self.shell, expression)?;
+                        let value = arithmetic::evaluate(self.shell, &expression)?;
+                        self.push_value(&value.to_string(), quoted);
+                    },
+                    None => self.substitute(&command, quoted)?
+                }
+            },
+            Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                let value = self.parameter(&name).unwrap_or_default();
+                self.push_value(&value, quoted);
+            },
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    // Replace a command substitution with the output of the command, without the trailing newlines
+    fn substitute(&mut self, command: &str, quoted: bool) -> Result<(), String> {
+        let output = command_execution::run_substitution(self.shell, command)?;
+        self.push_value(output.trim_end_matches('\n'), quoted);
+        Ok(())
+    }
+
+    // Expand ${...}: ${NAME}, ${#NAME} (the length of the value) or ${NAME<operator>word}
+    fn expand_braced(&mut self, inside: &str, quoted: bool) -> Result<(), String> {
+        let bad_substitution = || format!("${{{}}}: bad substitution", inside);
+        if let Some(name) = inside.strip_prefix('#').filter(|name| is_parameter_name(name)) {
+            let length = self.parameter(name).unwrap_or_default().chars().count();
+            self.push_value(&length.to_string(), quoted);
+            return Ok(());
+        }
+        // ${#NAME[@]} is the number of elements, ${#NAME[index]} the length of an element
+        if let Some((name, subscript)) = inside.strip_prefix('#').and_then(|inside| inside.strip_suffix(']')).and_then(|inside| inside.split_once('[')) {
+            if !is_valid_name(name) {
+                return Err(bad_substitution());
+            }
+            let elements = self.shell.variables.elements(name);
+            let length = match subscript {
+                "@" | "*" => elements.map_or(0, |elements| elements.len()),
+                _ => self.element(elements, subscript)?.unwrap_or_default().chars().count()
+            };
+            self.push_value(&length.to_string(), quoted);
+            return Ok(());
+        }
+        let name_length = if inside.starts_with(['?', '#', '@', '*']) {
+            1
+        } else if inside.starts_with(|c: char| c.is_ascii_digit()) {
+            inside.find(|c: char| !c.is_ascii_digit()).unwrap_or(inside.len())
+        } else {
+            inside.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(inside.len())
+        };
+        let (name, rest) = inside.split_at(name_length);
+        if !is_parameter_name(name) {
+            return Err(bad_substitution());
+        }
+        // NAME[index] is an element of an array, NAME[@] and NAME[*] are all of them
+        let (value, rest) = match rest.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+            Some((subscript, rest)) if is_valid_name(name) => {
+                let elements = self.shell.variables.elements(name);
+                if rest.is_empty() && matches!(subscript, "@" | "*") {
+                    self.push_elements(&elements.unwrap_or_default(), subscript == "@", quoted);
+                    return Ok(());
+                }
+                (self.element(elements, subscript)?, rest)
+            },
+            _ if rest.is_empty() && matches!(name, "@" | "*") => {
+                let positional = self.shell.positional.clone();
+                self.push_elements(&positional, name == "@", quoted);
+                return Ok(());
+            },
+            _ => (self.parameter(name), rest)
+        };
+        if rest.is_empty() {
+            self.push_value(&value.unwrap_or_default(), quoted);
+            return Ok(());
+        }
+        let Some(operator) = PARAMETER_OPERATORS.iter().find(|op| rest.starts_with(*op)) else {
+            return Err(bad_substitution());
+        };
+        let word = &rest[operator.len()..];
+        // with a colon an empty value counts as not set
+        let is_unset = match &value {
+            None => true,
+            Some(value) => operator.starts_with(':') && value.is_empty()
+        };
+        match *operator {
+            ":-" | "-" if is_unset => self.expand_inner_word(word, quoted)?,
+            ":+" | "+" if !is_unset => self.expand_inner_word(word, quoted)?,
+            ":+" | "+" => (),
+            ":=" | "=" if is_unset => {
+                if !is_valid_name(name) {
+                    return Err(format!("${}: cannot assign in this way", name));
+                }
+                let value = expand_word(self.shell, word)?;
+                self.shell.variables.set(name, &value)?;
+                self.push_value(&value, quoted);
+            },
+            ":?" | "?" if is_unset => {
+                let message = expand_word(self.shell, word)?;
+                let message = if message.is_empty() { String::from("parameter null or not set") } else { message };
+                return Err(format!("{}: {}", name, message));
+            },
+            ":-" | "-" | ":=" | "=" | ":?" | "?" => self.push_value(&value.unwrap_or_default(), quoted),
+            "%" | "%%" | "#" | "##" => {
+                let pattern = Pattern::make(&expand_pattern(self.shell, word)?);
+                let value = value.unwrap_or_default();
+                let trimmed = match *operator {
+                    "#" => remove_prefix(&value, &pattern, false),
+                    "##" => remove_prefix(&value, &pattern, true),
+                    "%" => remove_suffix(&value, &pattern, false),
+                    _ => remove_suffix(&value, &pattern, true)
+                };
+                self.push_value(trimmed, quoted);
+            },
+            _ => {
+                let (pattern, replacement) = split_replacement(word);
+                let pattern = Pattern::make(&expand_pattern(self.shell, pattern)?);
+                let replacement = expand_word(self.shell, replacement)?;
+                let replaced = replace(&value.unwrap_or_default(), &pattern, &replacement, operator);
+                self.push_value(&replaced, quoted);
+            }
+        }
+        Ok(())
+    }
+
+    // The element of an array at the index, an arithmetic expression. A negative index counts from the end
+    fn element(&mut self, elements: Option<Vec<String>>, subscript: &str) -> Result<Option<String>, String> {
+        let subscript = expand_word(self.shell, subscript)?;
+        let index = arithmetic::evaluate(self.shell, &subscript)?;
+        let elements = elements.unwrap_or_default();
+        let index = if index < 0 { index + elements.len() as i64 } else { index };
+        Ok(usize::try_from(index).ok().and_then(|index| elements.get(index)).cloned())
+    }
+
+    // Add all the elements of an array. Inside double quotes ${NAME[@]} makes each element a field of its own,
+    // ${NAME[*]} joins them with the first character of IFS
+    fn push_elements(&mut self, elements: &[String], separate: bool, quoted: bool) {
+        if elements.is_empty() && separate && quoted {
+            self.current.no_elements = true;
+        }
+        let separator = if separate { String::from(" ") } else { self.ifs.chars().next().map(String::from).unwrap_or_default() };
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                if separate && quoted && self.context == Context::Fields {
+                    self.finish_field();
+                    self.current.quoted = true;
+                } else {
+                    self.push_value(&separator, quoted);
+                }
+            }
+            self.push_value(element, quoted);
+        }
+    }
+
+    // Expand the word of ${NAME:-word} or ${NAME:+word} in place of the parameter
+    fn expand_inner_word(&mut self, word: &str, quoted: bool) -> Result<(), String> {
+        let outer = self.in_unquoted_word;
+        self.in_unquoted_word = !quoted;
+        let result = self.expand(word, quoted);
+        self.in_unquoted_word = outer;
+        result
+    }
+
+    fn into_fields(mut self) -> Vec<Field> {
+        if !self.current.text.is_empty() || (self.current.quoted && !self.current.no_elements) {
+            self.finish_field();
+        }
+        self.fields
+    }
+}
+
+// Read the inside of '${...}' up to the matching '}'
+fn read_braced(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut text = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('}', None) if depth == 0 => return Ok(text),
+            ('}', None) => depth -= 1,
+            ('{', None) if text.ends_with('$') => depth += 1,
+            ('\'', None) | ('"', None) => quote = Some(c),
+            (q, Some(open)) if q == open => quote = None,
+            ('\\', quote) if quote != Some('\'') => {
+                text.push(c);
+                match chars.next() {
+                    Some(escaped) => text.push(escaped),
+                    None => break
+                }
+                continue;
+            },
+            _ => ()
+        }
+        text.push(c);
+    }
+    Err(format!("${{{}: bad substitution", text))
+}
+
+// The home directory of the user from the user database, the current user if None
+fn home_directory(user: Option<&str>) -> Option<String> {
+    let entry = match user {
+        Some(user) => {
+            let name = CString::new(user).ok()?;
+            unsafe { libc::getpwnam(name.as_ptr()) }
+        },
+        None => unsafe { libc::getpwuid(libc::getuid()) }
+    };
+    if entry.is_null() {
+        return None;
+    }
+    let directory = unsafe { CStr::from_ptr((*entry).pw_dir) };
+    Some(directory.to_string_lossy().into_owned())
+}
+
+// Read the commands of '$(...)' up to the parenthesis that closes it
+fn read_parenthesized(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut text = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut cases = CaseTracker::make();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            // the parentheses around the patterns of a case command inside are no nesting
+            ('(' | ')', None) if cases.take(c) => (),
+            (')', None) if depth == 0 => return Ok(text),
+            (')', None) => depth -= 1,
+            ('(', None) => depth += 1,
+            ('\'', None) | ('"', None) | ('`', None) => {
+                cases.take_quoted();
+                quote = Some(c);
+            },
+            (q, Some(open)) if q == open => quote = None,
+            ('\\', quote) if quote != Some('\'') => {
+                cases.take_quoted();
+                text.push(c);
+                match chars.next() {
+                    Some(escaped) => text.push(escaped),
+                    None => break
+                }
+                continue;
+            },
+            (_, None) => { cases.take(c); },
+            _ => ()
+        }
+        text.push(c);
+    }
+    Err(format!("$({}: unterminated command substitution", text))
+}
+
+// Read the commands of '`...`' up to the closing backquote. A backslash before $, ` or \ is removed
+fn read_backquoted(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => return Ok(text),
+            '\\' => match chars.next() {
+                Some(escaped) if matches!(escaped, '$' | '`' | '\\') => text.push(escaped),
+                Some(escaped) => {
+                    text.push(c);
+                    text.push(escaped);
+                },
+                None => text.push(c)
+            },
+            _ => text.push(c)
+        }
+    }
+    Err(format!("`{}: unterminated command substitution", text))
+}
+
+// The parameters that can be given in braces: a variable name, the number of a positional parameter or one of the
+// special parameters ?, #, @ and *
+fn is_parameter_name(name: &str) -> bool {
+    matches!(name, "?" | "#" | "@" | "*") || (!name.is_empty() && name.chars().all(|c| c.is_ascii_digit())) || is_valid_name(name)
+}
+
+// The byte offsets of the character boundaries of the text, from the start to the end
+fn char_boundaries(text: &str) -> Vec<usize> {
+    text.char_indices().map(|(i, _)| i).chain(std::iter::once(text.len())).collect()
+}
+
+// Remove the shortest (or longest) start of the value that the pattern matches
+fn remove_prefix<'v>(value: &'v str, pattern: &Pattern, longest: bool) -> &'v str {
+    let mut ends = char_boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    match ends.into_iter().find(|end| pattern.matches(&value[..*end])) {
+        Some(end) => &value[end..],
+        None => value
+    }
+}
+
+// Remove the shortest (or longest) end of the value that the pattern matches
+fn remove_suffix<'v>(value: &'v str, pattern: &Pattern, longest: bool) -> &'v str {
+    let mut starts = char_boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    match starts.into_iter().find(|start| pattern.matches(&value[*start..])) {
+        Some(start) => &value[..start],
+        None => value
+    }
+}
+
+// Split the word of ${NAME/pattern/replacement} at the first unquoted '/'
+fn split_replacement(word: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in word.char_indices() {
+        match (c, quote) {
+            _ if escaped => escaped = false,
+            ('\\', q) if q != Some('\'') => escaped = true,
+            ('/', None) => return (&word[..i], &word[i + 1..]),
+            ('\'', None) | ('"', None) => quote = Some(c),
+            (q, Some(open)) if q == open => quote = None,
+            _ => ()
+        }
+    }
+    (word, "")
+}
+
+// Replace the longest matches of the pattern in the value. '/' replaces the first match, '//' all of them, '/#' a match
+// at the start and '/%' a match at the end
+fn replace(value: &str, pattern: &Pattern, replacement: &str, operator: &str) -> String {
+    if pattern.is_empty() {
+        return String::from(value);
+    }
+    let bounds = char_boundaries(value);
+    match operator {
+        "/#" => match bounds.iter().rev().find(|end| pattern.matches(&value[..**end])) {
+            Some(end) => format!("{}{}", replacement, &value[*end..]),
+            None => String::from(value)
+        },
+        "/%" => match bounds.iter().find(|start| pattern.matches(&value[**start..])) {
+            Some(start) => format!("{}{}", &value[..*start], replacement),
+            None => String::from(value)
+        },
+        _ => {
+            let mut result = String::new();
+            let mut i = 0;
+            while i + 1 < bounds.len() {
+                // the longest non-empty match starting here
+                let start = bounds[i];
+                let end = (i + 1..bounds.len()).rev().find(|end| pattern.matches(&value[start..bounds[*end]]));
+                match end {
+                    Some(end) => {
+                        result.push_str(replacement);
+                        if operator != "//" {
+                            result.push_str(&value[bounds[end]..]);
+                            return result;
+                        }
+                        i = end;
+                    },
+                    None => {
+                        result.push_str(&value[start..bounds[i + 1]]);
+                        i += 1;
+                    }
+                }
+            }
+            result
+        }
+    }
+}
+
+// Expand a single word to a single string, without splitting it into fields. Used for the assignments and the redirection targets.
+// Inside single quotes nothing is expanded, inside double quotes only the parameters and the command substitutions are
+pub fn expand_word(shell: &mut Shell, word: &str) -> Result<String, String> {
+    let mut expander = Expander::make(shell, Context::Text);
+    expander.expand(word, false)?;
+    Ok(expander.current.text)
+}
+
+// Expand the value of an assignment, NAME=value. Like a single word, but a '~' is expanded after every ':' too
+pub fn expand_assignment_value(shell: &mut Shell, value: &str) -> Result<String, String> {
+    let mut expander = Expander::make(shell, Context::Text);
+    expander.tilde_after_colon = true;
+    expander.expand(value, false)?;
+    Ok(expander.current.text)
+}
+
+// Expand a word used as a pattern. The quoted parts only match themselves
+pub fn expand_pattern(shell: &mut Shell, word: &str) -> Result<String, String> {
+    let mut expander = Expander::make(shell, Context::Text);
+    expander.expand(word, false)?;
+    Ok(expander.current.pattern)
+}
+
+// Expand a word used as an extended regular expression. Like a pattern, the quoted parts only match themselves
+pub fn expand_regex(shell: &mut Shell, word: &str) -> Result<String, String> {
+    let mut expander = Expander::make(shell, Context::Regex);
+    expander.expand(word, false)?;
+    Ok(expander.current.pattern)
+}
+
+// Expand a word to the fields it splits into. The braces are expanded first, each of the words they give is expanded on its own.
+// An unquoted word that expands to nothing gives no fields at all. A field with unquoted pattern characters is replaced
+// with the matching file names
+pub fn expand_word_to_fields(shell: &mut Shell, word: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    for word in braces::expand(word) {
+        let mut expander = Expander::make(shell, Context::Fields);
+        expander.expand(&word, false)?;
+        fields.extend(expander.into_fields());
+    }
+    let mut result = Vec::new();
+    for field in fields {
+        if !field.has_glob {
+            result.push(field.text);
+            continue;
+        }
+        let matches = glob::expand(&field.pattern, &shell.options);
+        if !matches.is_empty() {
+            result.extend(matches);
+        } else if shell.options.failglob {
+            return Err(format!("no match: {}", field.text));
+        } else if !shell.options.nullglob {
+            result.push(field.text);
+        }
+    }
+    Ok(result)
+}
+
+// Expand the lines of a here-document whose delimiter wasn't quoted. The quotes are taken literally, a backslash
+// only escapes $, `, \ and a newline
+pub fn expand_here_doc(shell: &mut Shell, body: &str) -> Result<String, String> {
+    let mut expander = Expander::make(shell, Context::Text);
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some('\n') => { chars.next(); },
+                Some('$') | Some('`') | Some('\\') => {
+                    let escaped = chars.next().unwrap();
+                    expander.push(escaped);
+                },
+                _ => expander.push(c)
+            },
+            '$' => {
+                if !expander.expand_dollar(&mut chars, true)? {
+                    expander.push(c);
+                }
+            },
+            '`' => {
+                let command = read_backquoted(&mut chars)?;
+                expander.substitute(&command, true)?;
+            },
+            _ => expander.push(c)
+        }
+    }
+    Ok(expander.current.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_removal() {
+        let mut shell = Shell::make(None);
+        assert_eq!(expand_word(&mut shell, r#"a\ b'c d'"e\"f""#).unwrap(), "a bc de\"f");
+        assert_eq!(expand_word(&mut shell, r#""a\b" 'x\y'"#).unwrap(), "a\\b x\\y");
+    }
+
+    #[test]
+    fn last_status() {
+        let mut shell = Shell::make(None);
+        shell.last_status = 3;
+        assert_eq!(expand_word(&mut shell, "$? \"$?\" '$?' \\$? $").unwrap(), "3 3 $? $? $");
+    }
+
+    #[test]
+    fn here_documents() {
+        let mut shell = Shell::make(None);
+        assert_eq!(expand_here_doc(&mut shell, "$? \"$?\" '$?' \\$? \\a\\\nb\n").unwrap(), "0 \"0\" '0' $? \\ab\n");
+    }
+
+    #[test]
+    fn variables_are_split_unless_quoted() {
+        let mut shell = Shell::make(None);
+        shell.variables.set("V", " a  b ").unwrap();
+        assert_eq!(expand_word(&mut shell, "x${V}y$V").unwrap(), "x a  b y a  b ");
+        assert_eq!(expand_word_to_fields(&mut shell, "x${V}y").unwrap(), ["x", "a", "b", "y"]);
+        assert_eq!(expand_word_to_fields(&mut shell, "\"$V\"").unwrap(), [" a  b "]);
+        assert_eq!(expand_word_to_fields(&mut shell, "$EXPANSION_TEST_UNSET").unwrap(), Vec::<String>::new());
+        assert_eq!(expand_word_to_fields(&mut shell, "\"$EXPANSION_TEST_UNSET\"").unwrap(), [""]);
+    }
+
+    #[test]
+    fn pattern_operators() {
+        let mut shell = Shell::make(None);
+        shell.variables.set("F", "archive.tar.gz").unwrap();
+        shell.variables.set("P", "/usr/local/bin").unwrap();
+        let cases = [
+            ("${F%.*}", "archive.tar"), ("${F%%.*}", "archive"), ("${F#*.}", "tar.gz"), ("${F##*.}", "gz"),
+            ("${F%.[gt]*}", "archive.tar"), ("${F/a/A}", "Archive.tar.gz"), ("${F//a/A}", "Archive.tAr.gz"),
+            ("${F/#arch/ARCH}", "ARCHive.tar.gz"), ("${F/#tar/X}", "archive.tar.gz"), ("${F/%gz/xz}", "archive.tar.xz"),
+            ("${P//\\//:}", ":usr:local:bin"), ("${F/\\*/x}", "archive.tar.gz"), ("${F%\"gz\"}", "archive.tar.")
+        ];
+        for (word, value) in cases {
+            assert_eq!(expand_word(&mut shell, word).unwrap(), value, "{}", word);
+        }
+    }
+
+    #[test]
+    fn default_values_and_lengths() {
+        let mut shell = Shell::make(None);
+        shell.variables.set("F", "archive").unwrap();
+        assert_eq!(expand_word(&mut shell, "${#F}|${EXPANSION_UNSET:-def}|${F:+set}|${EXPANSION_UNSET}").unwrap(), "7|def|set|");
+        assert_eq!(expand_word(&mut shell, "${EXPANSION_NEW:=new}").unwrap(), "new");
+        assert_eq!(shell.variables.get("EXPANSION_NEW"), Some("new"));
+        assert!(expand_word(&mut shell, "${EXPANSION_UNSET:?missing}").is_err());
+    }
+
+    #[test]
+    fn tilde_expansion() {
+        let mut shell = Shell::make(None);
+        shell.variables.set("HOME", "/home/me").unwrap();
+        shell.variables.set("OLDPWD", "/old").unwrap();
+        assert_eq!(expand_word(&mut shell, "~/a").unwrap(), "/home/me/a");
+        assert_eq!(expand_word(&mut shell, "~-").unwrap(), "/old");
+        assert_eq!(expand_word(&mut shell, "a~ \"~\" '~'").unwrap(), "a~ ~ ~");
+        assert_eq!(expand_word(&mut shell, "~no-such-user-here/x").unwrap(), "~no-such-user-here/x");
+        assert_eq!(expand_assignment_value(&mut shell, "~/bin:~/lib").unwrap(), "/home/me/bin:/home/me/lib");
+        assert_eq!(expand_word(&mut shell, "~/bin:~/lib").unwrap(), "/home/me/bin:~/lib");
+    }
+
+    #[test]
+    fn braces_come_before_the_other_expansions() {
+        let mut shell = Shell::make(None);
+        shell.variables.set("V", "v").unwrap();
+        assert_eq!(expand_word_to_fields(&mut shell, "{a,${V}}{1..2}").unwrap(), ["a1", "a2", "v1", "v2"]);
+        assert_eq!(expand_word_to_fields(&mut shell, "\"{a,b}\"").unwrap(), ["{a,b}"]);
+    }
+
+    fn shell_with_arguments(arguments: &[&str]) -> Shell {
+        let mut shell = Shell::make(None);
+        shell.positional = arguments.iter().map(|a| a.to_string()).collect();
+        shell
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let mut shell = shell_with_arguments(&["a", "b c"]);
+        assert_eq!(expand_word(&mut shell, "$1-${2}-$3-$#").unwrap(), "a-b c--2");
+        assert_eq!(expand_word_to_fields(&mut shell, "\"$@\"").unwrap(), ["a", "b c"]);
+        assert_eq!(expand_word_to_fields(&mut shell, "$*").unwrap(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn zero_digits_are_the_program_name() {
+        let mut shell = shell_with_arguments(&["a"]);
+        let program = std::env::args().next().unwrap();
+        assert_eq!(expand_word(&mut shell, "${00}").unwrap(), program);
+        assert_eq!(expand_word(&mut shell, "$0").unwrap(), program);
+        assert_eq!(expand_word(&mut shell, "${01}").unwrap(), "a");
+    }
+}
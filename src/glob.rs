@@ -0,0 +1,187 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::pattern::{self, Pattern};
+use crate::shell::ShellOptions;
+
+// Pathname expansion: a pattern is matched against the file names one path component at a time. '**' as a whole
+// component matches any number of directories. The names starting with a '.' are matched only by a pattern that starts
+// with a '.' too, unless dotglob is set
+
+// The names of the entries in the directory
+pub fn directory_entries(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        if let Some(file_name) = entry.file_name().to_str() {
+            names.push(String::from(file_name));
+        }
+    }
+    Ok(names)
+}
+
+fn join(base: &str, name: &str) -> String {
+    if base.is_empty() {
+        String::from(name)
+    } else if base.ends_with('/') {
+        format!("{}{}", base, name)
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
+
+// The directory to read for a path prefix, the prefix is empty for the working directory
+fn directory(base: &str) -> &Path {
+    Path::new(if base.is_empty() { "." } else { base })
+}
+
+fn is_directory(path: &str) -> bool {
+    fs::metadata(directory(path)).is_ok_and(|m| m.is_dir())
+}
+
+fn is_hidden_match_allowed(component: &str, options: &ShellOptions) -> bool {
+    options.dotglob || component.starts_with('.') || component.starts_with("\\.")
+}
+
+// The entries of the directory that a pattern component may match, sorted
+fn candidates(base: &str, component: &str, options: &ShellOptions) -> Vec<String> {
+    let mut names = directory_entries(directory(base)).unwrap_or_default();
+    if !is_hidden_match_allowed(component, options) {
+        names.retain(|name| !name.starts_with('.'));
+    }
+    names.sort();
+    names
+}
+
+// Every file and directory under the base, at any depth. The symbolic links to directories are not followed
+fn all_entries(base: &str, options: &ShellOptions, matches: &mut Vec<String>) {
+    for name in candidates(base, "*", options) {
+        let path = join(base, &name);
+        let is_real_directory = fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+        matches.push(path.clone());
+        if is_real_directory {
+            all_entries(&path, options, matches);
+        }
+    }
+}
+
+// Match the remaining pattern components under the base path
+fn expand_components(base: &str, components: &[&str], options: &ShellOptions, matches: &mut Vec<String>) {
+    let Some((component, rest)) = components.split_first() else {
+        matches.push(String::from(base));
+        return;
+    };
+    if component.is_empty() {
+        // a trailing slash or a repeated one, the path must be a directory
+        if is_directory(base) {
+            expand_components(&format!("{}/", base.trim_end_matches('/')), rest, options, matches);
+        }
+        return;
+    }
+    if *component == "**" {
+        if rest.is_empty() {
+            all_entries(base, options, matches);
+            return;
+        }
+        // no directories at all, then every directory in turn
+        expand_components(base, rest, options, matches);
+        for name in candidates(base, "*", options) {
+            let path = join(base, &name);
+            if fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir()) {
+                expand_components(&path, components, options, matches);
+            }
+        }
+        return;
+    }
+    if !pattern::has_special_chars(component) {
+        let path = join(base, &pattern::unescape(component));
+        let exists = if rest.is_empty() { fs::symlink_metadata(&path).is_ok() } else { is_directory(&path) };
+        if exists {
+            expand_components(&path, rest, options, matches);
+        }
+        return;
+    }
+    let compiled = Pattern::make(component);
+    for name in candidates(base, component, options) {
+        let path = join(base, &name);
+        if compiled.matches(&name) && (rest.is_empty() || is_directory(&path)) {
+            expand_components(&path, rest, options, matches);
+        }
+    }
+}
+
+// The paths matching the pattern, sorted. Empty if nothing matches
+pub fn expand(pattern: &str, options: &ShellOptions) -> Vec<String> {
+    let (base, relative) = match pattern.strip_prefix('/') {
+        Some(relative) => ("/", relative),
+        None => ("", pattern)
+    };
+    let components: Vec<&str> = relative.split('/').collect();
+    let mut matches = Vec::new();
+    expand_components(base, &components, options, &mut matches);
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory tree of its own for each test, removed when dropped
+    struct TestDir {
+        path: String
+    }
+
+    impl TestDir {
+        fn make(name: &str, files: &[&str]) -> Self {
+            let path = std::env::temp_dir().join(format!("versio2-glob-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            for file in files {
+                let file = path.join(file);
+                fs::create_dir_all(file.parent().unwrap()).unwrap();
+                fs::write(file, "").unwrap();
+            }
+            TestDir { path: path.to_string_lossy().into_owned() }
+        }
+
+        fn expand(&self, pattern: &str, options: &ShellOptions) -> Vec<String> {
+            let prefix = format!("{}/", self.path);
+            expand(&format!("{}{}", prefix, pattern), options).into_iter().map(|path| path.replacen(&prefix, "", 1)).collect()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn matches_are_sorted_and_hide_dot_files() {
+        let dir = TestDir::make("sorted", &["b.txt", "a.txt", "c.rs", ".hidden.txt", "sub/d.txt"]);
+        let options = ShellOptions::default();
+        assert_eq!(dir.expand("*.txt", &options), ["a.txt", "b.txt"]);
+        assert_eq!(dir.expand(".*.txt", &options), [".hidden.txt"]);
+        assert_eq!(dir.expand("?.[r-t]*", &options), ["a.txt", "b.txt", "c.rs"]);
+        assert_eq!(dir.expand("*/", &options), ["sub/"]);
+        assert_eq!(dir.expand("*/*.txt", &options), ["sub/d.txt"]);
+        assert!(dir.expand("*.md", &options).is_empty());
+        let dotglob = ShellOptions { dotglob: true, ..ShellOptions::default() };
+        assert_eq!(dir.expand("*.txt", &dotglob), [".hidden.txt", "a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn double_star_matches_any_depth() {
+        let dir = TestDir::make("recursive", &["a.rs", "x/b.rs", "x/y/c.rs", "x/y/d.txt"]);
+        let options = ShellOptions::default();
+        assert_eq!(dir.expand("**/*.rs", &options), ["a.rs", "x/b.rs", "x/y/c.rs"]);
+        assert_eq!(dir.expand("x/**", &options), ["x/b.rs", "x/y", "x/y/c.rs", "x/y/d.txt"]);
+    }
+
+    #[test]
+    fn escaped_characters_are_literal() {
+        let dir = TestDir::make("escaped", &["a*", "ab"]);
+        assert_eq!(dir.expand("a\\*", &ShellOptions::default()), ["a*"]);
+    }
+}
@@ -3,6 +3,10 @@ use crate::dirextory_prefix_tree::*;
 pub use crate::history::*;
 pub use crate::input_state_handler::*;
 
+// The control characters the terminal would normally turn into signals. The line editor gets them as input
+const INTERRUPT: char = '\x03';
+const SUSPEND: char = '\x1a';
+const QUIT: char = '\x1c';
 
 pub struct Input {
     input_buf: String,
@@ -92,7 +96,7 @@ impl Input {
         self.input_buf.push(c);
         self.buf_len += 1;
         self.input_buf_index += 1;
-        return Ok(());
+        Ok(())
     }
 
     fn erase_chars(&mut self, amount: usize) -> std::io::Result<()> {
@@ -101,7 +105,7 @@ impl Input {
             if self.input_buf_index == 0 {
                 break;
             }
-            print!("{} {}", '\u{8}', '\u{8}'); 
+            print!("\u{8} \u{8}"); 
             self.input_buf.pop();
             self.buf_len -= 1;
             self.input_buf_index -= 1;
@@ -121,7 +125,7 @@ impl Input {
         // observe the last part
         if let Some(last_part) = parts.last() {
             // match the last part against file names found in this directory
-            let longest_match = self.prefix_tree.find_longest_match(&last_part);
+            let longest_match = self.prefix_tree.find_longest_match(last_part);
             let last_pos = parts.len() - 1;
             // replace the last part
             parts[last_pos] = &longest_match;
@@ -140,7 +144,11 @@ impl Input {
         if c == '\t' {
             self.autocomplete_input_buf()?;
             return Ok(());
-        }        
+        }
+        // the terminal doesn't turn Ctrl-Z and Ctrl-\ into signals while the input is read
+        if c == SUSPEND || c == QUIT {
+            return Ok(());
+        }
         self.input_state.advance_state(c);
         match &self.input_state.current_state() {
             InputState::ANY => self.add_to_buf(c)?,
@@ -151,8 +159,8 @@ impl Input {
         Ok(())
     }
 
-    fn print_prompt(&self) -> Result<(),std::io::Error> {
-        print!("> ");
+    fn print_prompt(&self, prompt: &str) -> Result<(),std::io::Error> {
+        print!("{}", prompt);
         io::stdout().flush()?;
         Ok(())
     }
@@ -164,8 +172,17 @@ impl Input {
     }
 
     // Read user input byte by byte. Returns the input string after entering a newline
-    pub fn read_input(&mut self) -> std::io::Result<String> {
-        self.print_prompt()?;
+    pub fn read_input(&mut self, prompt: &str) -> std::io::Result<String> {
+        self.read_line(prompt)
+    }
+
+    // Read a line that continues the previous input (after an open quote or a trailing backslash)
+    pub fn read_continuation(&mut self) -> std::io::Result<String> {
+        self.read_line("... ")
+    }
+
+    fn read_line(&mut self, prompt: &str) -> std::io::Result<String> {
+        self.print_prompt(prompt)?;
 
         let mut buf =  vec![0; 1];
         let mut stdin = io::stdin();
@@ -173,6 +190,12 @@ impl Input {
         loop {
             stdin.read_exact(&mut buf)?; 
             let c = buf[0] as char;
+            if c == INTERRUPT {
+                // Ctrl-C discards the input
+                println!("^C");
+                self.reset();
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
             if c == '\n' {
                 let s = String::from(&self.input_buf);
                 self.store_to_history(s);
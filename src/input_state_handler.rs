@@ -1,4 +1,6 @@
 
+// the names of the states are kept as they were
+#[allow(clippy::upper_case_acronyms)]
 pub enum InputState {
     ESC,
     BRACK,
@@ -0,0 +1,505 @@
+use std::io;
+use termios::Termios;
+
+use crate::builtin_commands::BuiltinIo;
+use crate::shell::Shell;
+use crate::signals;
+
+// The exit status of a job that was stopped with Ctrl-Z, 128 + SIGTSTP
+pub const STOPPED_STATUS: i32 = 128 + libc::SIGTSTP;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    Stopped,
+    Done(i32)
+}
+
+pub struct Process {
+    pub pid: libc::pid_t,
+    pub state: ProcessState
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(i32)
+}
+
+// A job is a pipeline (or a copy of the interpreter running an and-or chain) in its own process group.
+// Ctrl-C and Ctrl-Z are delivered to the whole group when it is in the foreground
+pub struct Job {
+    pub id: usize,
+    pub pgid: libc::pid_t,
+    pub processes: Vec<Process>,
+    pub command: String,
+    // the terminal settings the job had when it was stopped, given back to it when it continues in the foreground
+    pub terminal_settings: Option<Termios>,
+    // the state that was last reported to the user
+    reported_state: JobState
+}
+
+impl Job {
+    pub fn make(pgid: libc::pid_t, pids: Vec<libc::pid_t>, command: String) -> Self {
+        let processes = pids.into_iter().map(|pid| Process { pid, state: ProcessState::Running }).collect();
+        Job { id: 0, pgid, processes, command, terminal_settings: None, reported_state: JobState::Running }
+    }
+
+    // A job is done when all its processes are done. Its exit status is the status of the last process
+    pub fn state(&self) -> JobState {
+        if self.processes.iter().any(|p| p.state == ProcessState::Running) {
+            return JobState::Running;
+        }
+        if self.processes.iter().any(|p| p.state == ProcessState::Stopped) {
+            return JobState::Stopped;
+        }
+        match self.processes.last() {
+            Some(Process { state: ProcessState::Done(status), .. }) => JobState::Done(*status),
+            _ => JobState::Done(0)
+        }
+    }
+
+    fn set_running(&mut self) {
+        for process in self.processes.iter_mut() {
+            if process.state == ProcessState::Stopped {
+                process.state = ProcessState::Running;
+            }
+        }
+    }
+
+    // Send SIGCONT to the whole process group
+    fn resume(&mut self) -> io::Result<()> {
+        if unsafe { libc::kill(-self.pgid, libc::SIGCONT) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        self.set_running();
+        Ok(())
+    }
+
+    // Wait like wait(true), but give up when Ctrl-C is pressed, see signals::catch_interrupt. Returns false if it was
+    pub fn wait_until_interrupted(&mut self) -> bool {
+        for process in self.processes.iter_mut() {
+            while process.state == ProcessState::Running {
+                if signals::take_interrupt() {
+                    return false;
+                }
+                if let Some(state) = wait_process_once(process.pid, libc::WUNTRACED) {
+                    process.state = state;
+                }
+            }
+        }
+        true
+    }
+
+    // Wait until every process of the job has finished, or stopped if report_stops is set. Without job control a stopped
+    // process is waited for until it continues and finishes
+    pub fn wait(&mut self, report_stops: bool) {
+        let flags = if report_stops { libc::WUNTRACED } else { 0 };
+        for process in self.processes.iter_mut() {
+            while process.state == ProcessState::Running {
+                process.state = wait_process(process.pid, flags);
+            }
+        }
+    }
+
+    // Collect the state changes of the processes without blocking
+    fn poll(&mut self) {
+        for process in self.processes.iter_mut() {
+            if !matches!(process.state, ProcessState::Done(_)) {
+                let mut raw_status = 0;
+                let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+                if unsafe { libc::waitpid(process.pid, &mut raw_status, flags) } == process.pid {
+                    process.state = decode_status(raw_status);
+                }
+            }
+        }
+    }
+
+    fn describe(&self, marker: char) -> String {
+        let state = match self.state() {
+            JobState::Running => String::from("Running"),
+            JobState::Stopped => String::from("Stopped"),
+            JobState::Done(0) => String::from("Done"),
+            JobState::Done(status) => format!("Exit {}", status)
+        };
+        format!("[{}]{}  {:<24}{}", self.id, marker, state, self.command)
+    }
+}
+
+// Turn a status given by waitpid into a process state
+fn decode_status(raw_status: i32) -> ProcessState {
+    if libc::WIFSTOPPED(raw_status) {
+        ProcessState::Stopped
+    } else if libc::WIFCONTINUED(raw_status) {
+        ProcessState::Running
+    } else if libc::WIFSIGNALED(raw_status) {
+        ProcessState::Done(128 + libc::WTERMSIG(raw_status))
+    } else {
+        ProcessState::Done(libc::WEXITSTATUS(raw_status))
+    }
+}
+
+// Block until the state of the process changes
+fn wait_process(pid: libc::pid_t, flags: libc::c_int) -> ProcessState {
+    loop {
+        if let Some(state) = wait_process_once(pid, flags) {
+            return state;
+        }
+    }
+}
+
+// Block until the state of the process changes or a signal arrives. None if a signal interrupted the wait
+fn wait_process_once(pid: libc::pid_t, flags: libc::c_int) -> Option<ProcessState> {
+    let mut raw_status = 0;
+    if unsafe { libc::waitpid(pid, &mut raw_status, flags) } != -1 {
+        return Some(decode_status(raw_status));
+    }
+    if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+        // the process can't be waited for anymore, e.g it was already collected
+        return Some(ProcessState::Done(1));
+    }
+    None
+}
+
+// Wait until the process finishes. Returns its exit status and the signal that killed it, if one did
+pub fn wait_for_exit(pid: libc::pid_t) -> (i32, Option<i32>) {
+    let mut raw_status = 0;
+    loop {
+        if unsafe { libc::waitpid(pid, &mut raw_status, 0) } == -1 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return (1, None);
+        }
+        if let ProcessState::Done(status) = decode_status(raw_status) {
+            return (status, libc::WIFSIGNALED(raw_status).then(|| libc::WTERMSIG(raw_status)));
+        }
+    }
+}
+
+// The jobs that run in the background or are stopped. The last one is the current job (marked with '+' in the listings)
+pub struct JobTable {
+    jobs: Vec<Job>
+}
+
+impl JobTable {
+    pub fn make() -> Self {
+        JobTable { jobs: Vec::new() }
+    }
+
+    // Add a job with the smallest free job number, or keep its number if it already had one (it was in the foreground
+    // for a while). Returns the number
+    pub fn add(&mut self, mut job: Job) -> usize {
+        let is_free = |id: usize| !self.jobs.iter().any(|j| j.id == id);
+        if job.id == 0 || !is_free(job.id) {
+            job.id = (1..).find(|id| is_free(*id)).unwrap();
+        }
+        let id = job.id;
+        self.jobs.push(job);
+        id
+    }
+
+    fn describe(&self, index: usize) -> String {
+        self.jobs[index].describe(self.marker(index))
+    }
+
+    fn print(&self, index: usize) {
+        println!("{}", self.describe(index));
+    }
+
+    fn marker(&self, index: usize) -> char {
+        if index + 1 == self.jobs.len() {
+            '+'
+        } else if index + 2 == self.jobs.len() {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    // Find the job matching the job specification: %N, %%, %+, %-, %prefix or a process group id. No specification means the current job
+    fn find(&self, spec: Option<&str>) -> Result<usize, String> {
+        let not_found = || format!("{}: no such job", spec.unwrap_or("current"));
+        let index = match spec {
+            None | Some("%%") | Some("%+") | Some("%") => self.jobs.len().checked_sub(1),
+            Some("%-") => self.jobs.len().checked_sub(2),
+            Some(s) => match s.strip_prefix('%') {
+                Some(rest) => match rest.parse::<usize>() {
+                    Ok(id) => self.jobs.iter().position(|j| j.id == id),
+                    Err(_) => self.jobs.iter().rposition(|j| j.command.starts_with(rest))
+                },
+                None => match s.parse::<libc::pid_t>() {
+                    Ok(pid) => self.jobs.iter().position(|j| j.pgid == pid || j.processes.iter().any(|p| p.pid == pid)),
+                    Err(_) => None
+                }
+            }
+        };
+        index.ok_or_else(not_found)
+    }
+
+    fn take(&mut self, spec: Option<&str>) -> Result<Job, String> {
+        let index = self.find(spec)?;
+        Ok(self.jobs.remove(index))
+    }
+
+    // Collect the state changes of the background jobs and report the ones that finished or stopped.
+    // The finished jobs are removed from the table
+    pub fn notify(&mut self) {
+        for job in self.jobs.iter_mut() {
+            job.poll();
+        }
+        let mut index = 0;
+        while index < self.jobs.len() {
+            let state = self.jobs[index].state();
+            if state != self.jobs[index].reported_state && state != JobState::Running {
+                self.print(index);
+            }
+            self.jobs[index].reported_state = state;
+            if matches!(state, JobState::Done(_)) {
+                self.jobs.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+// Wait for the job while it owns the terminal. If it stops, it is put into the job table.
+// Returns the exit status of the job
+pub fn run_in_foreground(shell: &mut Shell, mut job: Job, resume: bool) -> i32 {
+    if let Some(terminal) = &shell.terminal {
+        let _ = terminal.give_to(job.pgid);
+        match &job.terminal_settings {
+            Some(settings) => { let _ = terminal.apply_settings(settings); },
+            // the job was started in the background, it gets the user's settings like a new foreground job
+            None => { let _ = terminal.restore_original(); }
+        }
+    }
+    if resume {
+        if let Err(e) = job.resume() {
+            eprintln!("{}", e);
+        }
+    }
+    job.wait(shell.terminal.is_some());
+    if let Some(terminal) = &shell.terminal {
+        let _ = terminal.take_back();
+        if job.state() == JobState::Stopped {
+            job.terminal_settings = terminal.current_settings().ok();
+        }
+        let _ = terminal.restore_original();
+    }
+
+    match job.state() {
+        JobState::Stopped => {
+            job.reported_state = JobState::Stopped;
+            shell.jobs.add(job);
+            println!();
+            shell.jobs.print(shell.jobs.jobs.len() - 1);
+            STOPPED_STATUS
+        },
+        JobState::Done(status) => {
+            // the terminal echoed ^C or ^\, continue on a new line
+            if (status == 128 + libc::SIGINT || status == 128 + libc::SIGQUIT) && shell.terminal.is_some() {
+                println!();
+                shell.interrupted = status == 128 + libc::SIGINT;
+            }
+            status
+        },
+        JobState::Running => 0
+    }
+}
+
+// Put the job into the table without waiting for it. Prints the job number and the process group id
+pub fn run_in_background(shell: &mut Shell, job: Job) {
+    let pgid = job.pgid;
+    let id = shell.jobs.add(job);
+    if shell.terminal.is_some() {
+        println!("[{}] {}", id, pgid);
+    }
+}
+
+// The jobs builtin: list the jobs and their states
+pub fn jobs(shell: &mut Shell, _args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    for job in shell.jobs.jobs.iter_mut() {
+        job.poll();
+    }
+    for index in 0..shell.jobs.jobs.len() {
+        writeln!(streams.stdout, "{}", shell.jobs.describe(index))?;
+    }
+    for job in shell.jobs.jobs.iter_mut() {
+        job.reported_state = job.state();
+    }
+    shell.jobs.jobs.retain(|job| !matches!(job.state(), JobState::Done(_)));
+    Ok(0)
+}
+
+// The fg builtin: continue the job in the foreground
+pub fn fg(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    match shell.jobs.take(args.first().map(|s| s.as_str())) {
+        Ok(job) => {
+            writeln!(streams.stdout, "{}", job.command)?;
+            Ok(run_in_foreground(shell, job, true))
+        },
+        Err(e) => {
+            writeln!(streams.stderr, "fg: {}", e)?;
+            Ok(1)
+        }
+    }
+}
+
+// The bg builtin: continue the stopped jobs in the background
+pub fn bg(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let specs: Vec<Option<&str>> = if args.is_empty() {
+        vec![None]
+    } else {
+        args.iter().map(|s| Some(s.as_str())).collect()
+    };
+    let mut status = 0;
+    for spec in specs {
+        match shell.jobs.find(spec) {
+            Ok(index) => {
+                let marker = shell.jobs.marker(index);
+                let job = &mut shell.jobs.jobs[index];
+                match job.resume() {
+                    Ok(_) => {
+                        job.reported_state = JobState::Running;
+                        writeln!(streams.stdout, "[{}]{} {} &", job.id, marker, job.command)?;
+                    },
+                    Err(e) => {
+                        writeln!(streams.stderr, "bg: {}", e)?;
+                        status = 1;
+                    }
+                }
+            },
+            Err(e) => {
+                writeln!(streams.stderr, "bg: {}", e)?;
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+// Ctrl-C stopped the wait builtin. Like after a foreground job it ended, the rest of the input isn't run
+fn wait_interrupted(shell: &mut Shell) -> i32 {
+    if shell.terminal.is_some() {
+        println!();
+    }
+    shell.interrupted = true;
+    128 + libc::SIGINT
+}
+
+// The wait builtin: wait for the given jobs, or all of them, to finish. Returns the status of the last one waited for.
+// Ctrl-C stops waiting
+pub fn wait(shell: &mut Shell, args: &[String], streams: &mut BuiltinIo) -> io::Result<i32> {
+    let mut status = 0;
+    if args.is_empty() {
+        // the stopped jobs stay in the table
+        for index in 0..shell.jobs.jobs.len() {
+            let job = &mut shell.jobs.jobs[index];
+            if !job.wait_until_interrupted() {
+                shell.jobs.jobs.retain(|job| !matches!(job.state(), JobState::Done(_)));
+                return Ok(wait_interrupted(shell));
+            }
+            if let JobState::Done(s) = job.state() {
+                status = s;
+            }
+        }
+        shell.jobs.jobs.retain(|job| !matches!(job.state(), JobState::Done(_)));
+        return Ok(status);
+    }
+    for arg in args {
+        match shell.jobs.find(Some(arg)) {
+            Ok(index) => {
+                if !shell.jobs.jobs[index].wait_until_interrupted() {
+                    return Ok(wait_interrupted(shell));
+                }
+                status = match shell.jobs.jobs[index].state() {
+                    JobState::Done(s) => {
+                        shell.jobs.jobs.remove(index);
+                        s
+                    },
+                    // a stopped job stays in the table
+                    _ => STOPPED_STATUS
+                };
+            },
+            Err(e) => {
+                writeln!(streams.stderr, "wait: {}", e)?;
+                status = 127;
+            }
+        }
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(script: &str) -> libc::pid_t {
+        std::process::Command::new("sh").args(["-c", script]).spawn().unwrap().id() as libc::pid_t
+    }
+
+    fn table(commands: &[&str]) -> JobTable {
+        let mut table = JobTable::make();
+        for (i, command) in commands.iter().enumerate() {
+            let pid = 1000 + i as libc::pid_t;
+            table.add(Job::make(pid, vec![pid], command.to_string()));
+        }
+        table
+    }
+
+    #[test]
+    fn job_numbers_reuse_the_free_ones() {
+        let mut table = table(&["a", "b", "c"]);
+        let job = table.take(Some("%2")).unwrap();
+        assert_eq!(job.id, 2);
+        assert_eq!(table.add(Job::make(1, vec![1], String::from("d"))), 2);
+        assert_eq!(table.add(job), 4);
+    }
+
+    #[test]
+    fn job_specifications() {
+        let table = table(&["sleep 1", "vim x", "sleep 2"]);
+        assert_eq!(table.find(None), Ok(2));
+        assert_eq!(table.find(Some("%+")), Ok(2));
+        assert_eq!(table.find(Some("%-")), Ok(1));
+        assert_eq!(table.find(Some("%1")), Ok(0));
+        assert_eq!(table.find(Some("%vim")), Ok(1));
+        assert_eq!(table.find(Some("%sleep")), Ok(2));
+        assert_eq!(table.find(Some("1000")), Ok(0));
+        assert_eq!(table.find(Some("%4")), Err(String::from("%4: no such job")));
+        assert!(JobTable::make().find(None).is_err());
+    }
+
+    #[test]
+    fn markers_and_states() {
+        let mut table = table(&["a", "b", "c"]);
+        assert_eq!((table.marker(0), table.marker(1), table.marker(2)), (' ', '-', '+'));
+        table.jobs[2].processes[0].state = ProcessState::Stopped;
+        assert_eq!(table.jobs[2].state(), JobState::Stopped);
+        assert_eq!(table.jobs[2].describe('+'), format!("[3]+  {:<24}c", "Stopped"));
+        table.jobs[1].processes[0].state = ProcessState::Done(2);
+        assert_eq!(table.jobs[1].describe('-'), format!("[2]-  {:<24}b", "Exit 2"));
+    }
+
+    #[test]
+    fn waiting_reports_the_signal() {
+        assert_eq!(wait_for_exit(spawn("exit 3")), (3, None));
+        assert_eq!(wait_for_exit(spawn("kill -INT $$")), (128 + libc::SIGINT, Some(libc::SIGINT)));
+    }
+
+    #[test]
+    fn ctrl_c_stops_the_wait() {
+        let pid = spawn("exec sleep 10");
+        let mut job = Job::make(pid, vec![pid], String::from("sleep 10"));
+        signals::catch_interrupt();
+        unsafe { libc::raise(libc::SIGINT) };
+        assert!(!job.wait_until_interrupted());
+        assert_eq!(job.state(), JobState::Running);
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+        assert!(job.wait_until_interrupted());
+        assert_eq!(job.state(), JobState::Done(128 + libc::SIGKILL));
+    }
+}
@@ -5,60 +5,140 @@ mod history;
 mod builtin_commands;
 mod input_state_handler;
 mod dirextory_prefix_tree;
-use std::os::fd::RawFd;
+mod tokenizer;
+mod parser;
+mod shell;
+mod expansion;
+mod terminal;
+mod jobs;
+mod signals;
+mod redirection;
+mod variables;
+mod pattern;
+mod glob;
+mod braces;
+mod arithmetic;
+mod conditional;
+mod printf;
 use crate::input::Input;
-use builtin_commands::BUILTINS;
-use termios::*;
+use crate::shell::Shell;
+use crate::terminal::Terminal;
 
-static STDIN_FILENO: RawFd  = 0;
+// The exit status after Ctrl-C, 128 + SIGINT
+const INTERRUPTED_STATUS: i32 = 130;
 
-fn set_termios_settings() -> std::io::Result<termios::Termios> {
-    let old_terminal_settings = termios::Termios::from_fd(STDIN_FILENO)?;
-    let mut new_settings = old_terminal_settings;
-    // disable canonical mode. Also disable echo for better control of displayed input 
-    new_settings.c_lflag &= !(ICANON | ECHO);
-    // set new settings to stdin
-    termios::tcsetattr(STDIN_FILENO, TCSANOW, &new_settings)?;
-    Ok(old_terminal_settings)
+// The stack size of the thread that runs the interpreter. Functions and compound commands are run recursively, with
+// a few compound commands in a function body the default 8 MiB runs out long before the function nesting limit
+const EXECUTION_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// The prompt shows the exit status of the previous command if it failed
+fn prompt(shell: &Shell) -> String {
+    if shell.last_status != 0 {
+        format!("[{}] > ", shell.last_status)
+    } else {
+        String::from("> ")
+    }
 }
 
-pub fn split_by_whitespace(input_buf: &str) -> Vec<&str> {
-    input_buf.split_whitespace().collect()
+fn tokenize_and_parse(input_str: &str) -> Result<commands::CommandList, tokenizer::SyntaxError> {
+    parser::parse(tokenizer::tokenize(input_str)?)
 }
 
-fn main_loop() -> std::io::Result<()> {
+// Read lines until they form a complete input (no open quotes, trailing backslashes or pipes) and parse it
+fn read_command_list(inp: &mut Input, shell: &Shell) -> std::io::Result<Result<commands::CommandList, tokenizer::SyntaxError>> {
+    let mut input_str = inp.read_input(&prompt(shell))?;
+    loop {
+        match tokenize_and_parse(&input_str) {
+            Err(e) if e.incomplete => {
+                input_str.push('\n');
+                input_str.push_str(&inp.read_continuation()?);
+            },
+            result => return Ok(result)
+        }
+    }
+}
+
+fn main_loop(shell: &mut Shell) -> std::io::Result<()> {
     let mut inp = Input::make()?;
     loop {
-        if let Ok(input_str) = inp.read_input() {
-            if let Ok(builtin) = builtin_commands::check_builtin(&input_str) {
-                match builtin {
-                    BUILTINS::CD => {inp.update_prefix_tree()?; continue;},
-                    BUILTINS::EXIT => break,
-                    _ => ()
-                } 
+        shell.jobs.notify();
+        let read_result = match read_command_list(&mut inp, shell) {
+            Ok(read_result) => read_result,
+            Err(e) => {
+                // Ctrl-C at the prompt
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    shell.last_status = INTERRUPTED_STATUS;
+                }
+                continue;
+            }
+        };
+        match read_result {
+            Ok(list) => {
+                // the commands run with the user's terminal settings, Ctrl-C sends SIGINT to the interpreter while
+                // it runs a builtin
+                if let Some(terminal) = &shell.terminal {
+                    let _ = terminal.restore_original();
+                }
+                signals::take_interrupt();
+                command_execution::execute_list(shell, &list);
+                // a loop stopped by Ctrl-C has the status of its last run otherwise
+                if shell.interrupted {
+                    shell.last_status = INTERRUPTED_STATUS;
+                }
+                shell.interrupted = false;
+                if let Some(terminal) = &shell.terminal {
+                    let _ = terminal.enter_shell_mode();
+                }
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                shell.last_status = 2;
             }
-            
-            command_execution::execute_commands(
-                &mut commands::make_commands(
-                    split_by_whitespace(&input_str)
-                )
-            );
+        }
+        if shell.dir_changed {
+            inp.update_prefix_tree()?;
+            shell.dir_changed = false;
+        }
+        if shell.exit_requested {
+            break;
         }
     }
     Ok(())
 }
 
-fn main() {
-    if let Ok(old_terminal_settings) = set_termios_settings() {
-        if let Err(_) = main_loop() {
-            eprintln!("Failed to initialize");
+// Set up the terminal and run the interpreter until it exits, returns the exit status
+fn run() -> i32 {
+    let terminal = match Terminal::make() {
+        Ok(terminal) if terminal.enter_shell_mode().is_ok() => terminal,
+        _ => {
+            eprintln!("Couldn't set up terminal settings");
+            return 1;
         }
-        // Restore old terminal settings.
-        match termios::tcsetattr(STDIN_FILENO, TCSANOW, &old_terminal_settings) {
-            Ok(_) => (),
-            Err(_) => eprintln!("failed to restore terminal settings")
+    };
+    let mut shell = Shell::make(Some(terminal));
+    if main_loop(&mut shell).is_err() {
+        eprintln!("Failed to initialize");
+    }
+    // Restore old terminal settings.
+    if let Some(terminal) = &shell.terminal {
+        if terminal.restore_original().is_err() {
+            eprintln!("failed to restore terminal settings");
         }
-    } else {
-        eprintln!("Couldn't set up terminal settings");
     }
+    // 'exit N' ends the interpreter with the status N, a plain 'exit' with the status of the last command
+    shell.last_status
+}
+
+fn main() {
+    signals::ignore_job_control_signals();
+    signals::ignore_interrupt_signals();
+    signals::catch_interrupt();
+    let status = match std::thread::Builder::new().stack_size(EXECUTION_STACK_SIZE).spawn(run) {
+        Ok(interpreter) => interpreter.join().unwrap_or(1),
+        Err(e) => {
+            eprintln!("Couldn't start the interpreter: {}", e);
+            1
+        }
+    };
+    std::process::exit(status);
 }
@@ -0,0 +1,602 @@
+use std::rc::Rc;
+
+use crate::commands::*;
+use crate::conditional;
+use crate::tokenizer::{here_doc_delimiter, SyntaxError, Token, TokenKind, RESERVED_WORDS};
+use crate::variables::{is_valid_name, split_assignment};
+
+// A recursive descent parser for the grammar
+//
+// list           : linebreak (and_or (separator linebreak and_or)* separator?)? linebreak
+// separator      : '&' | ';' | NEWLINE
+// linebreak      : NEWLINE*
+// and_or         : pipeline (('&&' | '||') linebreak pipeline)*
+// pipeline       : '!'? command ('|' linebreak command)*
+// command        : simple_command | compound_command redirection* | function_definition
+// function_definition : NAME '(' ')' linebreak function_body | 'function' NAME ('(' ')')? linebreak function_body
+// function_body  : '{' list '}'
+// compound_command : ARITHMETIC | CONDITIONAL | if_command | loop_command | for_command | case_command | subshell | brace_group
+// subshell       : '(' list ')'
+// brace_group    : '{' list '}'
+// if_command     : 'if' list 'then' list ('elif' list 'then' list)* ('else' list)? 'fi'
+// loop_command   : ('while' | 'until') list do_group
+// for_command    : 'for' NAME linebreak ('in' WORD* (';' | NEWLINE))? linebreak do_group
+//                | 'for' ARITHMETIC (';' | NEWLINE)? linebreak do_group
+// do_group       : 'do' list 'done'
+// case_command   : 'case' WORD linebreak 'in' linebreak case_item* 'esac'
+// case_item      : '('? WORD ('|' WORD)* ')' list (';;' linebreak)?
+// simple_command : (ASSIGNMENT | redirection)* (WORD | redirection)*, not empty
+//
+// The lists inside the compound commands end at the reserved word that follows them, e.g 'then' or 'done'.
+// A reserved word is only recognized where a command name could be
+// redirection    : IO_NUMBER? ('<' | '>' | '>|' | '>>' | '<>' | '<&' | '>&' | '<<' | '<<-' | '<<<') WORD
+//                | ('&>' | '&>>') WORD
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize
+}
+
+impl Parser {
+    fn make(tokens: Vec<Token>) -> Self {
+        Parser { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn peek_is_operator(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(t) if t.is_operator(op))
+    }
+
+    // whether the token at the current position is the reserved word
+    fn peek_is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(t) if t.kind == TokenKind::Word && t.text == word)
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek_is_operator("\n") {
+            self.next();
+        }
+    }
+
+    // an error about the token at the current position
+    fn unexpected(&self) -> SyntaxError {
+        match self.peek() {
+            Some(token) if token.is_operator("\n") => SyntaxError::make("unexpected newline", token.span.line, token.span.column),
+            Some(token) => SyntaxError::make(&format!("unexpected '{}'", token.text), token.span.line, token.span.column),
+            None => self.unexpected_end()
+        }
+    }
+
+    // skip the reserved word that must be at the current position
+    fn expect_word(&mut self, word: &str) -> Result<(), SyntaxError> {
+        if !self.peek_is_word(word) {
+            return Err(match self.peek() {
+                Some(token) => SyntaxError::make(&format!("expected '{}'", word), token.span.line, token.span.column),
+                None => self.unexpected_end()
+            });
+        }
+        self.next();
+        Ok(())
+    }
+
+    // whether the current list ends here: at the end of the input, at one of the reserved words that end the enclosing
+    // command, at a ')' or at the ';;' of a case item
+    fn at_list_end(&self, terminators: &[&str]) -> bool {
+        match self.peek() {
+            None => true,
+            Some(token) => token.is_operator(")") || token.is_operator(";;")
+                || (token.kind == TokenKind::Word && terminators.contains(&token.text.as_str()))
+        }
+    }
+
+    // the input ended too early. Reading more input might complete the command
+    fn unexpected_end(&self) -> SyntaxError {
+        let (line, column) = match self.tokens.last() {
+            Some(token) => (token.span.line, token.span.column + token.text.chars().count()),
+            None => (1, 1)
+        };
+        SyntaxError::make_incomplete("unexpected end of input", line, column)
+    }
+
+    // parse the and-or chains up to the end of the list, see at_list_end
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<CommandList, SyntaxError> {
+        let mut items = Vec::new();
+        self.skip_newlines();
+        while !self.at_list_end(terminators) {
+            let and_or = self.parse_and_or()?;
+            let background = self.peek_is_operator("&");
+            if background || self.peek_is_operator(";") || self.peek_is_operator("\n") {
+                self.next();
+            } else if !self.at_list_end(terminators) {
+                return Err(self.unexpected());
+            }
+            items.push(ListItem { and_or, background });
+            self.skip_newlines();
+        }
+        Ok(CommandList { items })
+    }
+
+    // parse the list inside a compound command, which must have at least one command
+    fn parse_compound_list(&mut self, terminators: &[&str]) -> Result<CommandList, SyntaxError> {
+        let list = self.parse_list(terminators)?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, SyntaxError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let operator = if self.peek_is_operator("&&") {
+                AndOrOperator::And
+            } else if self.peek_is_operator("||") {
+                AndOrOperator::Or
+            } else {
+                break;
+            };
+            self.next();
+            self.skip_newlines();
+            if self.at_end() {
+                return Err(self.unexpected_end());
+            }
+            rest.push((operator, self.parse_pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, SyntaxError> {
+        let negated = self.peek_is_word("!");
+        if negated {
+            self.next();
+        }
+        let mut commands = vec![self.parse_command()?];
+        while self.peek_is_operator("|") {
+            self.next();
+            self.skip_newlines();
+            if self.at_end() {
+                return Err(self.unexpected_end());
+            }
+            commands.push(self.parse_command()?);
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, SyntaxError> {
+        let Some(token) = self.peek() else {
+            return Err(self.unexpected_end());
+        };
+        let compound = match (token.kind, token.text.as_str()) {
+            (TokenKind::Arithmetic, expression) => {
+                let expression = String::from(expression);
+                self.next();
+                CompoundCommand::Arithmetic(expression)
+            },
+            (TokenKind::Conditional, expression) => {
+                conditional::check_syntax(expression).map_err(|e| SyntaxError::make(&e, token.span.line, token.span.column))?;
+                let expression = String::from(expression);
+                self.next();
+                CompoundCommand::Conditional(expression)
+            },
+            (TokenKind::Word, "if") => self.parse_if()?,
+            (TokenKind::Word, "while" | "until") => self.parse_loop()?,
+            (TokenKind::Word, "for") => self.parse_for()?,
+            (TokenKind::Word, "case") => self.parse_case()?,
+            (TokenKind::Operator, "(") => {
+                self.next();
+                let list = self.parse_compound_list(&[])?;
+                if !self.peek_is_operator(")") {
+                    return Err(self.unexpected());
+                }
+                self.next();
+                CompoundCommand::Subshell(list)
+            },
+            (TokenKind::Word, "{") => self.parse_brace_group()?,
+            (TokenKind::Word, "function") => return self.parse_function_definition(),
+            (TokenKind::Word, _) if self.at_empty_parentheses(1) => return self.parse_function_definition(),
+            // the other reserved words can't start a command
+            (TokenKind::Word, word) if RESERVED_WORDS.contains(&word) && word != "in" => return Err(self.unexpected()),
+            _ => return Ok(Command::Simple(self.parse_simple_command()?))
+        };
+        let mut redirections = Vec::new();
+        while let Some(redirection) = self.parse_redirection()? {
+            redirections.extend(redirection);
+        }
+        Ok(Command::Compound(compound, redirections))
+    }
+
+    fn parse_brace_group(&mut self) -> Result<CompoundCommand, SyntaxError> {
+        self.expect_word("{")?;
+        let list = self.parse_compound_list(&["}"])?;
+        self.expect_word("}")?;
+        Ok(CompoundCommand::BraceGroup(list))
+    }
+
+    // whether '()' comes this many tokens after the current position
+    fn at_empty_parentheses(&self, offset: usize) -> bool {
+        let is_operator = |offset: usize, op: &str| self.tokens.get(self.pos + offset).is_some_and(|t| t.is_operator(op));
+        is_operator(offset, "(") && is_operator(offset + 1, ")")
+    }
+
+    fn parse_function_definition(&mut self) -> Result<Command, SyntaxError> {
+        if self.peek_is_word("function") {
+            self.next();
+        }
+        let Some(name) = self.peek().filter(|t| t.kind == TokenKind::Word).cloned() else {
+            return Err(self.unexpected());
+        };
+        if !is_valid_name(&name.text) || RESERVED_WORDS.contains(&name.text.as_str()) {
+            return Err(SyntaxError::make(&format!("'{}': not a valid function name", name.text), name.span.line, name.span.column));
+        }
+        self.next();
+        if self.at_empty_parentheses(0) {
+            self.next();
+            self.next();
+        }
+        self.skip_newlines();
+        self.expect_word("{")?;
+        let body = self.parse_compound_list(&["}"])?;
+        self.expect_word("}")?;
+        Ok(Command::FunctionDefinition(FunctionDefinition { name: name.text, body: Rc::new(body) }))
+    }
+
+    fn parse_if(&mut self) -> Result<CompoundCommand, SyntaxError> {
+        self.expect_word("if")?;
+        let mut branches = Vec::new();
+        let mut else_body = None;
+        loop {
+            let condition = self.parse_compound_list(&["then"])?;
+            self.expect_word("then")?;
+            let body = self.parse_compound_list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            if self.peek_is_word("elif") {
+                self.next();
+                continue;
+            }
+            if self.peek_is_word("else") {
+                self.next();
+                else_body = Some(self.parse_compound_list(&["fi"])?);
+            }
+            break;
+        }
+        self.expect_word("fi")?;
+        Ok(CompoundCommand::If(IfCommand { branches, else_body }))
+    }
+
+    fn parse_do_group(&mut self) -> Result<CommandList, SyntaxError> {
+        self.expect_word("do")?;
+        let body = self.parse_compound_list(&["done"])?;
+        self.expect_word("done")?;
+        Ok(body)
+    }
+
+    fn parse_loop(&mut self) -> Result<CompoundCommand, SyntaxError> {
+        let until = self.peek_is_word("until");
+        self.next();
+        let condition = self.parse_compound_list(&["do"])?;
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::Loop(LoopCommand { until, condition, body }))
+    }
+
+    fn parse_for(&mut self) -> Result<CompoundCommand, SyntaxError> {
+        self.expect_word("for")?;
+        let Some(token) = self.next().cloned() else {
+            return Err(self.unexpected_end());
+        };
+        if token.kind == TokenKind::Arithmetic {
+            let parts: Vec<&str> = token.text.split(';').collect();
+            let [init, condition, step] = parts[..] else {
+                return Err(SyntaxError::make("expected 'for ((init; condition; step))'", token.span.line, token.span.column));
+            };
+            if self.peek_is_operator(";") {
+                self.next();
+            }
+            self.skip_newlines();
+            let body = self.parse_do_group()?;
+            let (init, condition, step) = (String::from(init.trim()), String::from(condition.trim()), String::from(step.trim()));
+            return Ok(CompoundCommand::ArithmeticFor(ArithmeticForCommand { init, condition, step, body }));
+        }
+        if token.kind != TokenKind::Word || !is_valid_name(&token.text) {
+            return Err(SyntaxError::make(&format!("'{}': not a valid loop variable name", token.text), token.span.line, token.span.column));
+        }
+        self.skip_newlines();
+        let mut words = None;
+        if self.peek_is_word("in") {
+            self.next();
+            let mut list = Vec::new();
+            while let Some(word) = self.peek().filter(|t| t.kind == TokenKind::Word) {
+                list.push(word.text.clone());
+                self.next();
+            }
+            if !self.peek_is_operator(";") && !self.peek_is_operator("\n") {
+                return Err(self.unexpected());
+            }
+            self.next();
+            words = Some(list);
+        } else if self.peek_is_operator(";") {
+            self.next();
+        }
+        self.skip_newlines();
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For(ForCommand { name: token.text, words, body }))
+    }
+
+    fn parse_case(&mut self) -> Result<CompoundCommand, SyntaxError> {
+        self.expect_word("case")?;
+        let Some(word) = self.peek().filter(|t| t.kind == TokenKind::Word).map(|t| t.text.clone()) else {
+            return Err(self.unexpected());
+        };
+        self.next();
+        self.skip_newlines();
+        self.expect_word("in")?;
+        self.skip_newlines();
+        let mut items = Vec::new();
+        while !self.peek_is_word("esac") {
+            if self.peek_is_operator("(") {
+                self.next();
+            }
+            let mut patterns = Vec::new();
+            loop {
+                let Some(pattern) = self.peek().filter(|t| t.kind == TokenKind::Word).map(|t| t.text.clone()) else {
+                    return Err(self.unexpected());
+                };
+                patterns.push(pattern);
+                self.next();
+                if !self.peek_is_operator("|") {
+                    break;
+                }
+                self.next();
+            }
+            if !self.peek_is_operator(")") {
+                return Err(self.unexpected());
+            }
+            self.next();
+            let body = self.parse_list(&["esac"])?;
+            items.push(CaseItem { patterns, body });
+            if self.peek_is_operator(";;") {
+                self.next();
+                self.skip_newlines();
+            } else if !self.peek_is_word("esac") {
+                return Err(self.unexpected());
+            }
+        }
+        self.next();
+        Ok(CompoundCommand::Case(CaseCommand { word, items }))
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SingleCommand, SyntaxError> {
+        let mut command = SingleCommand::make();
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Word => {
+                    // the words of the form NAME=value before the command name are assignments
+                    match split_assignment(&token.text).filter(|_| command.words.is_empty()) {
+                        Some((name, value)) => command.assignments.push(Assignment { name: String::from(name), value: String::from(value) }),
+                        None => command.words.push(token.text.clone())
+                    }
+                    self.next();
+                },
+                TokenKind::Operator | TokenKind::IoNumber => match self.parse_redirection()? {
+                    Some(redirections) => command.redirections.extend(redirections),
+                    None => break
+                },
+                TokenKind::Arithmetic | TokenKind::Conditional => break
+            }
+        }
+        if command.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(command)
+    }
+
+    // parse a redirection if there is one at the current position. '&>' stands for two redirections, the output to the file
+    // and the error output to the output
+    fn parse_redirection(&mut self) -> Result<Option<Vec<Redirection>>, SyntaxError> {
+        let io_number = match self.peek() {
+            Some(token) if token.kind == TokenKind::IoNumber => {
+                let fd = token.text.parse::<i32>()
+                    .map_err(|_| SyntaxError::make("file descriptor out of range", token.span.line, token.span.column))?;
+                self.next();
+                Some(fd)
+            },
+            _ => None
+        };
+        let Some(operator) = self.peek().filter(|t| t.kind == TokenKind::Operator).cloned() else {
+            return Ok(None);
+        };
+        let (kind, with_error_output) = match operator.text.as_str() {
+            "<" => (RedirectionKind::Input, false),
+            ">" | ">|" => (RedirectionKind::Output, false),
+            ">>" => (RedirectionKind::Append, false),
+            "<>" => (RedirectionKind::ReadWrite, false),
+            "<&" => (RedirectionKind::DuplicateInput, false),
+            ">&" => (RedirectionKind::DuplicateOutput, false),
+            "<<" | "<<-" => (RedirectionKind::HereDoc, false),
+            "<<<" => (RedirectionKind::HereString, false),
+            "&>" if io_number.is_none() => (RedirectionKind::Output, true),
+            "&>>" if io_number.is_none() => (RedirectionKind::Append, true),
+            _ if io_number.is_none() => return Ok(None),
+            _ => return Err(self.unexpected())
+        };
+        self.next();
+        let (target, here_doc) = match self.next() {
+            Some(token) if token.kind == TokenKind::Word => {
+                let here_doc = token.here_doc.clone().map(|body| HereDocument { body, expand: !here_doc_delimiter(&token.text).1 });
+                (token.text.clone(), here_doc)
+            },
+            Some(token) => return Err(SyntaxError::make(&format!("expected a file name after '{}'", operator.text), token.span.line, token.span.column)),
+            None => return Err(SyntaxError::make(&format!("expected a file name after '{}'", operator.text), operator.span.line, operator.span.column))
+        };
+        let mut redirections = vec![Redirection { fd: io_number.unwrap_or(kind.default_fd()), kind, target, here_doc }];
+        if with_error_output {
+            redirections.push(Redirection { fd: 2, kind: RedirectionKind::DuplicateOutput, target: String::from("1"), here_doc: None });
+        }
+        Ok(Some(redirections))
+    }
+}
+
+// Build the syntax tree from the input tokens
+pub fn parse(tokens: Vec<Token>) -> Result<CommandList, SyntaxError> {
+    let mut parser = Parser::make(tokens);
+    let list = parser.parse_list(&[])?;
+    if !parser.at_end() {
+        return Err(parser.unexpected());
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    fn parse_input(input: &str) -> Result<CommandList, SyntaxError> {
+        tokenize(input).and_then(parse)
+    }
+
+    fn single_pipeline(input: &str) -> Pipeline {
+        let mut list = parse_input(input).unwrap();
+        assert_eq!(list.items.len(), 1);
+        let and_or = list.items.remove(0).and_or;
+        assert!(and_or.rest.is_empty());
+        and_or.first
+    }
+
+    fn compound(input: &str) -> (CompoundCommand, Vec<Redirection>) {
+        match single_pipeline(input).commands.remove(0) {
+            Command::Compound(compound, redirections) => (compound, redirections),
+            command => panic!("not a compound command: {}", command)
+        }
+    }
+
+    #[test]
+    fn lists_and_and_or_chains() {
+        let list = parse_input("a && b || c; d &\ne").unwrap();
+        assert_eq!(list.items.len(), 3);
+        let operators: Vec<AndOrOperator> = list.items[0].and_or.rest.iter().map(|(operator, _)| *operator).collect();
+        assert_eq!(operators, [AndOrOperator::And, AndOrOperator::Or]);
+        assert!(!list.items[0].background);
+        assert!(list.items[1].background);
+        assert_eq!(list.to_string(), "a && b || c; d & e");
+        assert_eq!(parse_input("{ a; { b & } }").unwrap().to_string(), "{ a; { b & }; }");
+    }
+
+    #[test]
+    fn pipelines() {
+        let pipeline = single_pipeline("! a | b |\n c");
+        assert!(pipeline.negated);
+        assert_eq!(pipeline.commands.len(), 3);
+    }
+
+    #[test]
+    fn simple_commands() {
+        let Command::Simple(command) = single_pipeline("cmd 'arg' 2>err <in 3>&- &>>all").commands.remove(0) else {
+            panic!("not a simple command");
+        };
+        assert_eq!(command.words, ["cmd", "'arg'"]);
+        let redirections: Vec<(i32, RedirectionKind, &str)> = command.redirections.iter().map(|r| (r.fd, r.kind, r.target.as_str())).collect();
+        assert_eq!(redirections, [
+            (2, RedirectionKind::Output, "err"), (0, RedirectionKind::Input, "in"), (3, RedirectionKind::DuplicateOutput, "-"),
+            (1, RedirectionKind::Append, "all"), (2, RedirectionKind::DuplicateOutput, "1")
+        ]);
+    }
+
+    #[test]
+    fn if_commands() {
+        let (CompoundCommand::If(command), _) = compound("if a; then b; elif c\nthen d; else e; fi") else {
+            panic!("not an if command");
+        };
+        assert_eq!(command.branches.len(), 2);
+        assert_eq!(command.else_body.map(|body| body.to_string()).as_deref(), Some("e"));
+    }
+
+    #[test]
+    fn loops_with_redirections() {
+        let (CompoundCommand::Loop(command), redirections) = compound("until a; do b; done > out") else {
+            panic!("not a loop");
+        };
+        assert!(command.until);
+        assert_eq!(redirections.len(), 1);
+        let (CompoundCommand::For(command), _) = compound("for x in 1 2; do echo $x; done") else {
+            panic!("not a for loop");
+        };
+        assert_eq!((command.name.as_str(), command.words), ("x", Some(vec![String::from("1"), String::from("2")])));
+        let (CompoundCommand::ArithmeticFor(command), _) = compound("for ((i = 0; i < 3; i++)) do :; done") else {
+            panic!("not an arithmetic for loop");
+        };
+        assert_eq!((command.init.trim(), command.condition.trim(), command.step.trim()), ("i = 0", "i < 3", "i++"));
+    }
+
+    #[test]
+    fn case_commands() {
+        let (CompoundCommand::Case(command), _) = compound("case $x in\n(a|b) one;;\n*) two\nesac") else {
+            panic!("not a case command");
+        };
+        let patterns: Vec<Vec<String>> = command.items.iter().map(|item| item.patterns.clone()).collect();
+        assert_eq!(patterns, [vec![String::from("a"), String::from("b")], vec![String::from("*")]]);
+    }
+
+    #[test]
+    fn brace_groups() {
+        let (CompoundCommand::BraceGroup(list), _) = compound("{ a; { b & } }") else {
+            panic!("not a brace group");
+        };
+        assert_eq!(list.items.len(), 2);
+        let Command::Simple(command) = single_pipeline("echo { }").commands.remove(0) else {
+            panic!("not a simple command");
+        };
+        assert_eq!(command.words, ["echo", "{", "}"]);
+    }
+
+    #[test]
+    fn subshells_and_brace_groups() {
+        let pipeline = single_pipeline("(cd a; make) | { sort; } 2>/dev/null");
+        assert!(matches!(&pipeline.commands[0], Command::Compound(CompoundCommand::Subshell(list), _) if list.items.len() == 2));
+        assert!(matches!(&pipeline.commands[1], Command::Compound(CompoundCommand::BraceGroup(_), redirections) if redirections.len() == 1));
+        assert_eq!(pipeline.to_string(), "(cd a; make) | { sort; } 2> /dev/null");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let error = parse_input("a && ; b").unwrap_err();
+        assert_eq!((error.message.as_str(), error.line, error.column, error.incomplete), ("unexpected ';'", 1, 6, false));
+        let error = parse_input("a && fi").unwrap_err();
+        assert_eq!((error.message.as_str(), error.line, error.column, error.incomplete), ("unexpected 'fi'", 1, 6, false));
+        assert!(parse_input("a &&\n").is_err_and(|e| e.incomplete));
+        assert!(parse_input("if a; then b").is_err_and(|e| e.incomplete));
+        assert!(parse_input("a )").is_err_and(|e| !e.incomplete));
+        assert!(parse_input("a | ").is_err_and(|e| e.incomplete));
+        assert!(parse_input("{ a;").is_err_and(|e| e.incomplete));
+        assert!(parse_input("; a").is_err_and(|e| !e.incomplete));
+        assert!(parse_input("{ }").is_err());
+        assert!(parse_input("(a").is_err_and(|e| e.incomplete));
+        assert!(parse_input("( )").is_err());
+        let error = parse_input("a; [[ -f ]]").unwrap_err();
+        assert_eq!((error.message.as_str(), error.column, error.incomplete), ("argument expected after '-f'", 4, false));
+    }
+
+    #[test]
+    fn function_definitions() {
+        for input in ["f() { echo; }", "function f { echo; }", "function f ()\n{ echo; }"] {
+            let Command::FunctionDefinition(function) = single_pipeline(input).commands.remove(0) else {
+                panic!("not a function definition: {}", input);
+            };
+            assert_eq!(function.name, "f");
+        }
+        assert!(parse_input("if() { echo; }").is_err());
+        assert!(parse_input("f() echo").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+// Shell patterns: '*' matches any string, '?' any single character and '[...]' any character of the set. A set can hold
+// ranges (a-z) and classes ([:alpha:]), '[!...]' or '[^...]' matches the characters not in the set.
+// A backslash makes the next character literal
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SetItem {
+    Char(char),
+    Range(char, char),
+    Class(String)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PatternPart {
+    Literal(char),
+    AnyChar,
+    AnyString,
+    Set { negated: bool, items: Vec<SetItem> }
+}
+
+// A compiled pattern
+pub struct Pattern {
+    parts: Vec<PatternPart>
+}
+
+fn class_matches(class: &str, c: char) -> bool {
+    match class {
+        "alpha" => c.is_alphabetic(),
+        "digit" => c.is_ascii_digit(),
+        "alnum" => c.is_alphanumeric(),
+        "upper" => c.is_uppercase(),
+        "lower" => c.is_lowercase(),
+        "space" => c.is_whitespace(),
+        "blank" => c == ' ' || c == '\t',
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        "cntrl" => c.is_control(),
+        "print" => !c.is_control(),
+        "graph" => !c.is_control() && !c.is_whitespace(),
+        _ => false
+    }
+}
+
+impl PatternPart {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            PatternPart::Literal(l) => *l == c,
+            PatternPart::AnyChar => true,
+            PatternPart::AnyString => true,
+            PatternPart::Set { negated, items } => {
+                let in_set = items.iter().any(|item| match item {
+                    SetItem::Char(s) => *s == c,
+                    SetItem::Range(from, to) => *from <= c && c <= *to,
+                    SetItem::Class(class) => class_matches(class, c)
+                });
+                in_set != *negated
+            }
+        }
+    }
+}
+
+// Parse the set that starts after a '['. Returns the set and the position after its ']', or None if the set isn't closed
+// (the '[' is then a literal character)
+fn parse_set(chars: &[char], start: usize) -> Option<(PatternPart, usize)> {
+    let mut pos = start;
+    let negated = matches!(chars.get(pos), Some('!') | Some('^'));
+    if negated {
+        pos += 1;
+    }
+    let mut items = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(pos)?;
+        match c {
+            // a ']' right at the start is a member of the set
+            ']' if !first => return Some((PatternPart::Set { negated, items }, pos + 1)),
+            '[' if chars.get(pos + 1) == Some(&':') => {
+                let rest: String = chars[pos + 2..].iter().collect();
+                let end = rest.find(":]")?;
+                items.push(SetItem::Class(String::from(&rest[..end])));
+                pos += 2 + rest[..end].chars().count() + 2;
+            },
+            _ => {
+                let (c, next) = match c {
+                    '\\' => (*chars.get(pos + 1)?, pos + 2),
+                    _ => (c, pos + 1)
+                };
+                if chars.get(next) == Some(&'-') && chars.get(next + 1).is_some_and(|e| *e != ']') {
+                    let (end, after) = match chars[next + 1] {
+                        '\\' => (*chars.get(next + 2)?, next + 3),
+                        e => (e, next + 2)
+                    };
+                    items.push(SetItem::Range(c, end));
+                    pos = after;
+                } else {
+                    items.push(SetItem::Char(c));
+                    pos = next;
+                }
+            }
+        }
+        first = false;
+    }
+}
+
+// Whether the pattern has characters that match something else than themselves
+pub fn has_special_chars(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' | '[' => return true,
+            '\\' => { chars.next(); },
+            _ => ()
+        }
+    }
+    false
+}
+
+// The pattern with the escaping backslashes removed, i.e the text a pattern without special characters matches
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next().unwrap_or(c)),
+            _ => text.push(c)
+        }
+    }
+    text
+}
+
+// Make the character literal in a pattern
+pub fn escape_char(c: char, pattern: &mut String) {
+    if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+        pattern.push('\\');
+    }
+    pattern.push(c);
+}
+
+impl Pattern {
+    pub fn make(pattern: &str) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parts = Vec::new();
+        let mut pos = 0;
+        while pos < chars.len() {
+            let c = chars[pos];
+            pos += 1;
+            match c {
+                '*' => {
+                    // a run of stars is the same as one
+                    if parts.last() != Some(&PatternPart::AnyString) {
+                        parts.push(PatternPart::AnyString);
+                    }
+                },
+                '?' => parts.push(PatternPart::AnyChar),
+                '[' => match parse_set(&chars, pos) {
+                    Some((set, next)) => {
+                        parts.push(set);
+                        pos = next;
+                    },
+                    None => parts.push(PatternPart::Literal('['))
+                },
+                '\\' if pos < chars.len() => {
+                    parts.push(PatternPart::Literal(chars[pos]));
+                    pos += 1;
+                },
+                _ => parts.push(PatternPart::Literal(c))
+            }
+        }
+        Pattern { parts }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    // Whether the pattern matches the whole text. A '*' first matches as little as possible and takes more
+    // characters only when the rest of the pattern fails
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let (mut p, mut t) = (0, 0);
+        // the position of the last '*' and the text position it is tried at
+        let mut backtrack: Option<(usize, usize)> = None;
+        while t < text.len() {
+            match self.parts.get(p) {
+                Some(PatternPart::AnyString) => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                },
+                Some(part) if part.matches(text[t]) => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                },
+                _ => ()
+            }
+            match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                },
+                None => return false
+            }
+        }
+        self.parts[p..].iter().all(|part| *part == PatternPart::AnyString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::make(pattern).matches(text)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.txt", "notes.txt"));
+        assert!(matches("*.txt", ".txt"));
+        assert!(!matches("*.txt", "notes.txt.bak"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(matches("?", "é"));
+        assert!(!matches("??", "a"));
+        assert!(matches("**", ""));
+    }
+
+    #[test]
+    fn sets() {
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[!a-c]", "d"));
+        assert!(matches("[^a-c]", "d"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[[:digit:][:upper:]]*", "Q1"));
+        assert!(!matches("[[:digit:]]", "a"));
+        // an unclosed '[' is literal
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(has_special_chars("a[b"));
+        assert!(!has_special_chars("a\\*b"));
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+        let mut escaped = String::new();
+        "a*[?".chars().for_each(|c| escape_char(c, &mut escaped));
+        assert!(matches(&escaped, "a*[?"));
+    }
+}
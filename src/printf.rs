@@ -0,0 +1,432 @@
+// The formatting of the printf builtin and the escape sequences shared with echo -e. A format is reused until all the
+// arguments are consumed, a missing argument counts as an empty string or 0
+
+// Expand the backslash escapes: \a \b \e \f \n \r \t \v \\, \xHH and octal values. The octal values are written \0NNN
+// for echo and %b, \NNN in a printf format. Returns the text and whether a \c asked to stop all the output
+pub fn expand_escapes(text: &str, octal_needs_zero: bool) -> (String, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' || i + 1 == chars.len() {
+            result.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        i += 2;
+        match chars[i - 1] {
+            'a' => result.push('\x07'),
+            'b' => result.push('\x08'),
+            'e' | 'E' => result.push('\x1b'),
+            'f' => result.push('\x0c'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            'v' => result.push('\x0b'),
+            '\\' => result.push('\\'),
+            'c' => return (result, true),
+            'x' if chars.get(i).is_some_and(|c| c.is_ascii_hexdigit()) => {
+                let digits: String = chars[i..].iter().take(2).take_while(|c| c.is_ascii_hexdigit()).collect();
+                i += digits.len();
+                result.push(u8::from_str_radix(&digits, 16).unwrap_or(0) as char);
+            },
+            c if c.is_digit(8) && (c == '0' || !octal_needs_zero) => {
+                // the leading 0 of \0NNN doesn't count as one of the three digits
+                let start = if octal_needs_zero { i } else { i - 1 };
+                let digits: String = chars[start..].iter().take(3).take_while(|c| c.is_digit(8)).collect();
+                i = start + digits.len();
+                result.push(u8::from_str_radix(&digits, 8).map(|b| b as char).unwrap_or('\0'));
+            },
+            c => {
+                result.push('\\');
+                result.push(c);
+            }
+        }
+    }
+    (result, false)
+}
+
+// A conversion specification, %[flags][width][.precision]conversion
+struct Specification {
+    left_align: bool,
+    plus_sign: bool,
+    space_sign: bool,
+    alternate: bool,
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char
+}
+
+// The arguments of printf, consumed one at a time
+struct Arguments<'a> {
+    args: &'a [String],
+    pos: usize,
+    errors: Vec<String>
+}
+
+impl Arguments<'_> {
+    fn next(&mut self) -> Option<&str> {
+        let arg = self.args.get(self.pos)?;
+        self.pos += 1;
+        Some(arg)
+    }
+
+    fn next_text(&mut self) -> String {
+        self.next().map(String::from).unwrap_or_default()
+    }
+
+    // An integer argument: decimal, octal with a leading 0, hexadecimal with 0x, or 'c (the code of the character c)
+    fn next_integer(&mut self) -> i64 {
+        let Some(arg) = self.next().map(String::from) else {
+            return 0;
+        };
+        let text = arg.trim();
+        if let Some(quoted) = text.strip_prefix('\'').or_else(|| text.strip_prefix('"')) {
+            return quoted.chars().next().map(|c| c as i64).unwrap_or(0);
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text))
+        };
+        let parsed = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            i64::from_str_radix(hex, 16)
+        } else if digits.len() > 1 && digits.starts_with('0') {
+            i64::from_str_radix(&digits[1..], 8)
+        } else {
+            digits.parse::<i64>()
+        };
+        match parsed {
+            Ok(value) if negative => value.wrapping_neg(),
+            Ok(value) => value,
+            Err(_) => {
+                self.errors.push(format!("{}: invalid number", arg));
+                0
+            }
+        }
+    }
+
+    fn next_float(&mut self) -> f64 {
+        let Some(arg) = self.next().map(String::from) else {
+            return 0.0;
+        };
+        let text = arg.trim();
+        if let Some(quoted) = text.strip_prefix('\'').or_else(|| text.strip_prefix('"')) {
+            return quoted.chars().next().map(|c| c as u32 as f64).unwrap_or(0.0);
+        }
+        text.parse::<f64>().unwrap_or_else(|_| {
+            self.errors.push(format!("{}: invalid number", arg));
+            0.0
+        })
+    }
+}
+
+// Pad the text to the width of the specification. The zeros of zero padding go after the sign or prefix
+fn pad(spec: &Specification, prefix: &str, body: &str, zero_pad: bool) -> String {
+    let len = prefix.chars().count() + body.chars().count();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left_align {
+        format!("{}{}{}", prefix, body, " ".repeat(fill))
+    } else if zero_pad {
+        format!("{}{}{}", prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), prefix, body)
+    }
+}
+
+fn sign(spec: &Specification, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus_sign {
+        "+"
+    } else if spec.space_sign {
+        " "
+    } else {
+        ""
+    }
+}
+
+fn format_integer(spec: &Specification, value: i64) -> String {
+    let (prefix, digits) = match spec.conversion {
+        'd' | 'i' => (sign(spec, value < 0), value.unsigned_abs().to_string()),
+        'o' => (if spec.alternate { "0" } else { "" }, format!("{:o}", value as u64)),
+        'x' => (if spec.alternate && value != 0 { "0x" } else { "" }, format!("{:x}", value as u64)),
+        'X' => (if spec.alternate && value != 0 { "0X" } else { "" }, format!("{:X}", value as u64)),
+        _ => ("", (value as u64).to_string())
+    };
+    // the precision is the minimum number of digits
+    let digits = match spec.precision {
+        Some(0) if value == 0 => String::new(),
+        Some(precision) if digits.len() < precision => format!("{}{}", "0".repeat(precision - digits.len()), digits),
+        _ => digits
+    };
+    pad(spec, prefix, &digits, spec.zero_pad && spec.precision.is_none())
+}
+
+// Format in the %e style of C: one digit before the point and an exponent of at least two digits
+fn exponent_notation(value: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let text = format!("{}e{}{:02}", mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs());
+    if upper { text.to_uppercase() } else { text }
+}
+
+fn format_float(spec: &Specification, value: f64) -> String {
+    let precision = spec.precision.unwrap_or(6);
+    let magnitude = value.abs();
+    let body = if !value.is_finite() {
+        let text = if value.is_nan() { "nan" } else { "inf" };
+        if spec.conversion.is_uppercase() { text.to_uppercase() } else { String::from(text) }
+    } else {
+        match spec.conversion {
+            'e' | 'E' => exponent_notation(magnitude, precision, spec.conversion == 'E'),
+            'g' | 'G' => {
+                // the shorter of %f and %e for the number of significant digits, without the trailing zeros
+                let significant = precision.max(1);
+                let exponent = exponent_notation(magnitude, significant - 1, false);
+                let exponent: i32 = exponent.rsplit_once('e').and_then(|(_, e)| e.parse().ok()).unwrap_or(0);
+                let mut text = if exponent < -4 || exponent >= significant as i32 {
+                    exponent_notation(magnitude, significant - 1, spec.conversion == 'G')
+                } else {
+                    format!("{:.*}", (significant as i32 - 1 - exponent).max(0) as usize, magnitude)
+                };
+                if !spec.alternate {
+                    let (number, exponent) = match text.find(['e', 'E']) {
+                        Some(index) => (text[..index].to_string(), text[index..].to_string()),
+                        None => (text.clone(), String::new())
+                    };
+                    let number = if number.contains('.') { number.trim_end_matches('0').trim_end_matches('.').to_string() } else { number };
+                    text = format!("{}{}", number, exponent);
+                }
+                text
+            },
+            _ => format!("{:.*}", precision, magnitude)
+        }
+    };
+    let zero_pad = spec.zero_pad && value.is_finite();
+    pad(spec, sign(spec, value < 0.0), &body, zero_pad)
+}
+
+// Format the arguments once with the format. Returns the output and whether a \c in a %b argument or an invalid
+// specification stopped the output
+fn format_once(format: &[char], args: &mut Arguments) -> (String, bool) {
+    let mut output = String::new();
+    let mut i = 0;
+    while i < format.len() {
+        match format[i] {
+            '\\' => {
+                let length = escape_length(&format[i..]);
+                let (escape, _) = expand_escapes(&format[i..i + length].iter().collect::<String>(), false);
+                output.push_str(&escape);
+                i += length;
+            },
+            '%' if format.get(i + 1) == Some(&'%') => {
+                output.push('%');
+                i += 2;
+            },
+            '%' => {
+                let (spec, next) = parse_specification(format, i + 1, args);
+                i = next;
+                let spec = match spec {
+                    Ok(spec) => spec,
+                    Err(e) => {
+                        args.errors.push(e);
+                        return (output, true);
+                    }
+                };
+                match spec.conversion {
+                    's' => {
+                        let text = args.next_text();
+                        let text: String = match spec.precision {
+                            Some(precision) => text.chars().take(precision).collect(),
+                            None => text
+                        };
+                        output.push_str(&pad(&spec, "", &text, false));
+                    },
+                    'b' => {
+                        let (text, stop) = expand_escapes(&args.next_text(), true);
+                        output.push_str(&pad(&spec, "", &text, false));
+                        if stop {
+                            return (output, true);
+                        }
+                    },
+                    'c' => {
+                        let text: String = args.next_text().chars().take(1).collect();
+                        output.push_str(&pad(&spec, "", &text, false));
+                    },
+                    'd' | 'i' | 'o' | 'u' | 'x' | 'X' => {
+                        let value = args.next_integer();
+                        output.push_str(&format_integer(&spec, value));
+                    },
+                    _ => {
+                        let value = args.next_float();
+                        output.push_str(&format_float(&spec, value));
+                    }
+                }
+            },
+            c => {
+                output.push(c);
+                i += 1;
+            }
+        }
+    }
+    (output, false)
+}
+
+// The number of characters of the escape sequence at the start of the text, the backslash included
+fn escape_length(text: &[char]) -> usize {
+    match text.get(1) {
+        None => 1,
+        Some('x') => 2 + text[2..].iter().take(2).take_while(|c| c.is_ascii_hexdigit()).count(),
+        Some(c) if c.is_digit(8) => 1 + text[1..].iter().take(3).take_while(|c| c.is_digit(8)).count(),
+        Some(_) => 2
+    }
+}
+
+// The largest width or precision, the formatting of the floating point numbers takes no larger precision
+const MAX_FIELD: usize = u16::MAX as usize;
+
+// Read the digits of a width or precision, moving i past them. None if the number is out of range
+fn read_field_number(format: &[char], i: &mut usize) -> Option<usize> {
+    let mut number: usize = 0;
+    while let Some(digit) = format.get(*i).and_then(|c| c.to_digit(10)) {
+        number = number.saturating_mul(10).saturating_add(digit as usize);
+        *i += 1;
+    }
+    (number <= MAX_FIELD).then_some(number)
+}
+
+// Parse the specification after a '%'. A '*' width or precision is taken from the arguments.
+// Returns the specification, or the error about an unknown conversion or a width or precision out of range, and the
+// position after it
+fn parse_specification(format: &[char], start: usize, args: &mut Arguments) -> (Result<Specification, String>, usize) {
+    let mut spec = Specification {
+        left_align: false, plus_sign: false, space_sign: false, alternate: false, zero_pad: false, width: 0, precision: None, conversion: 's'
+    };
+    let mut error = None;
+    let mut i = start;
+    while let Some(c) = format.get(i) {
+        match c {
+            '-' => spec.left_align = true,
+            '+' => spec.plus_sign = true,
+            ' ' => spec.space_sign = true,
+            '#' => spec.alternate = true,
+            '0' => spec.zero_pad = true,
+            _ => break
+        }
+        i += 1;
+    }
+    if format.get(i) == Some(&'*') {
+        let width = args.next_integer();
+        spec.left_align |= width < 0;
+        spec.width = width.unsigned_abs() as usize;
+        if spec.width > MAX_FIELD {
+            error = Some(format!("{}: invalid field width", width));
+        }
+        i += 1;
+    } else {
+        let digits_start = i;
+        match read_field_number(format, &mut i) {
+            Some(width) => spec.width = width,
+            None => error = Some(format!("{}: invalid field width", format[digits_start..i].iter().collect::<String>()))
+        }
+    }
+    if format.get(i) == Some(&'.') {
+        i += 1;
+        if format.get(i) == Some(&'*') {
+            let precision = args.next_integer();
+            spec.precision = Some(precision.clamp(0, MAX_FIELD as i64) as usize);
+            if precision > MAX_FIELD as i64 {
+                error = error.or(Some(format!("{}: invalid precision", precision)));
+            }
+            i += 1;
+        } else {
+            let digits_start = i;
+            match read_field_number(format, &mut i) {
+                Some(precision) => spec.precision = Some(precision),
+                None => error = error.or(Some(format!("{}: invalid precision", format[digits_start..i].iter().collect::<String>())))
+            }
+        }
+    }
+    let result = match format.get(i) {
+        Some(c) if "sbcdiouxXeEfFgG".contains(*c) => {
+            spec.conversion = *c;
+            error.map_or(Ok(spec), Err)
+        },
+        Some(c) => Err(format!("%{}: invalid format character", c)),
+        None => Err(String::from("%: missing format character"))
+    };
+    (result, (i + 1).min(format.len()))
+}
+
+// Format the arguments, reusing the format until they are all consumed. Returns the output and the errors about
+// invalid arguments
+pub fn format(format: &str, args: &[String]) -> (String, Vec<String>) {
+    let format: Vec<char> = format.chars().collect();
+    let mut arguments = Arguments { args, pos: 0, errors: Vec::new() };
+    let mut output = String::new();
+    loop {
+        let start = arguments.pos;
+        let (text, stop) = format_once(&format, &mut arguments);
+        output.push_str(&text);
+        // a format without conversions would never consume the arguments
+        if stop || arguments.pos >= args.len() || arguments.pos == start {
+            break;
+        }
+    }
+    (output, arguments.errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printf(format_text: &str, args: &[&str]) -> (String, Vec<String>) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        format(format_text, &args)
+    }
+
+    #[test]
+    fn the_format_is_reused_for_the_arguments() {
+        assert_eq!(printf("%s-%d|", &["a", "5", "b", "6"]).0, "a-5|b-6|");
+        assert_eq!(printf("%s %s\n", &["only"]).0, "only \n");
+        assert_eq!(printf("no conversions\n", &["x"]).0, "no conversions\n");
+    }
+
+    #[test]
+    fn conversions() {
+        let (output, errors) = printf("[%5.2f][%-4s][%04d][%x][%X][%o][%e][%+d][%c][%%]", &["3.14159", "ab", "42", "255", "255", "8", "1234.5", "3", "xyz"]);
+        assert_eq!(output, "[ 3.14][ab  ][0042][ff][FF][10][1.234500e+03][+3][x][%]");
+        assert!(errors.is_empty());
+        assert_eq!(printf("%d", &["'A"]).0, "65");
+        assert_eq!(printf("%*d|%-*s|", &["4", "7", "3", "a"]).0, "   7|a  |");
+    }
+
+    #[test]
+    fn invalid_numbers_are_reported() {
+        let (output, errors) = printf("%d\n", &["abc"]);
+        assert_eq!(output, "0\n");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn invalid_specifications_are_reported() {
+        assert_eq!(printf("a%.*f|", &["99999999999", "1"]), (String::from("a"), vec![String::from("99999999999: invalid precision")]));
+        assert_eq!(printf("%*d|", &["-99999999999", "1"]).1, ["-99999999999: invalid field width"]);
+        assert_eq!(printf("%d|%99999999999999999999d|%d", &["1"]), (String::from("1|"), vec![String::from("99999999999999999999: invalid field width")]));
+        assert_eq!(printf("%.70000f", &["1"]).1, ["70000: invalid precision"]);
+        assert_eq!(printf("a%", &[]), (String::from("a"), vec![String::from("%: missing format character")]));
+        assert_eq!(printf("%5", &[]).1, ["%: missing format character"]);
+        assert_eq!(printf("a%zb", &[]), (String::from("a"), vec![String::from("%z: invalid format character")]));
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(printf("\\101\\x41\\n", &[]).0, "AA\n");
+        assert_eq!(printf("a%bc", &["x\\cy", "z"]).0, "ax");
+        assert_eq!(expand_escapes("a\\tb\\\\", true), (String::from("a\tb\\"), false));
+        assert_eq!(expand_escapes("\\0101\\101", true), (String::from("A\\101"), false));
+        assert_eq!(expand_escapes("stop\\c here", false), (String::from("stop"), true));
+    }
+}
@@ -0,0 +1,200 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::builtin_commands;
+use crate::commands::{Redirection, RedirectionKind};
+use crate::expansion::{expand_here_doc, expand_word};
+use crate::shell::Shell;
+
+// The opened files are moved to descriptors from this number up so that they don't collide with the descriptors
+// the redirections write to
+const FIRST_PRIVATE_FD: RawFd = 10;
+
+// A redirection resolved to a file descriptor operation
+#[derive(Debug, Clone, Copy)]
+pub enum FdAction {
+    // make fd a copy of source
+    Duplicate { source: RawFd, fd: RawFd },
+    Close(RawFd)
+}
+
+// The redirections of a command with their files opened. The files must stay open until the actions have been applied
+pub struct PreparedRedirections {
+    _files: Vec<File>,
+    pub actions: Vec<FdAction>
+}
+
+// Move the descriptor of a file above the descriptors used by the redirections
+fn move_to_private_fd(file: File) -> io::Result<File> {
+    let fd = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_DUPFD_CLOEXEC, FIRST_PRIVATE_FD) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn open_file(kind: RedirectionKind, path: &str) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    match kind {
+        RedirectionKind::Input => options.read(true),
+        RedirectionKind::Output => options.write(true).create(true).truncate(true),
+        RedirectionKind::Append => options.append(true).create(true),
+        RedirectionKind::ReadWrite => options.read(true).write(true).create(true).truncate(false),
+        // '>&file' and '<&file' with a name instead of a number
+        RedirectionKind::DuplicateOutput => options.write(true).create(true).truncate(true),
+        RedirectionKind::DuplicateInput | RedirectionKind::HereDoc | RedirectionKind::HereString => options.read(true)
+    };
+    options.open(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, builtin_commands::error_message(&e))))
+}
+
+// Store the text to an unnamed temporary file and return the file, positioned at the start, for reading.
+// Unlike a pipe, the file doesn't need a reader running at the same time no matter how long the text is
+fn text_file(text: &str) -> io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!("here-doc-{}-{}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let mut file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+    // the file stays usable after its name is removed and disappears when it is closed
+    fs::remove_file(&path)?;
+    file.write_all(text.as_bytes())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+// Open the files of the redirections in the order they were written and turn the redirections into descriptor operations
+pub fn prepare(shell: &mut Shell, redirections: &[Redirection]) -> io::Result<PreparedRedirections> {
+    let mut files = Vec::new();
+    let mut actions = Vec::new();
+    for redirection in redirections {
+        let fd = redirection.fd;
+        let text = match (redirection.kind, &redirection.here_doc) {
+            (RedirectionKind::HereDoc, Some(here_doc)) if here_doc.expand => Some(expand_here_doc(shell, &here_doc.body).map_err(io::Error::other)?),
+            (RedirectionKind::HereDoc, Some(here_doc)) => Some(here_doc.body.clone()),
+            (RedirectionKind::HereString, _) => Some(expand_word(shell, &redirection.target).map_err(io::Error::other)? + "\n"),
+            _ => None
+        };
+        if let Some(text) = text {
+            let file = move_to_private_fd(text_file(&text)?)?;
+            actions.push(FdAction::Duplicate { source: file.as_raw_fd(), fd });
+            files.push(file);
+            continue;
+        }
+
+        let target = expand_word(shell, &redirection.target).map_err(io::Error::other)?;
+        let is_duplication = matches!(redirection.kind, RedirectionKind::DuplicateInput | RedirectionKind::DuplicateOutput);
+        if is_duplication && target == "-" {
+            actions.push(FdAction::Close(fd));
+        } else if let (true, Ok(source)) = (is_duplication, target.parse::<RawFd>()) {
+            actions.push(FdAction::Duplicate { source, fd });
+        } else if redirection.kind == RedirectionKind::DuplicateOutput && fd == 1 {
+            // '>&file' means the output and the error output to the file
+            let file = move_to_private_fd(open_file(redirection.kind, &target)?)?;
+            actions.push(FdAction::Duplicate { source: file.as_raw_fd(), fd: 1 });
+            actions.push(FdAction::Duplicate { source: 1, fd: 2 });
+            files.push(file);
+        } else if is_duplication {
+            return Err(io::Error::other(format!("{}: ambiguous redirect", target)));
+        } else {
+            let file = move_to_private_fd(open_file(redirection.kind, &target)?)?;
+            actions.push(FdAction::Duplicate { source: file.as_raw_fd(), fd });
+            files.push(file);
+        }
+    }
+    Ok(PreparedRedirections { _files: files, actions })
+}
+
+fn perform(action: FdAction) -> io::Result<()> {
+    let result = match action {
+        FdAction::Duplicate { source, fd } if source == fd => 0,
+        FdAction::Duplicate { source, fd } => unsafe { libc::dup2(source, fd) },
+        FdAction::Close(fd) => unsafe { libc::close(fd) }
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Apply the operations in order. Called in a child process right before it starts the program, so only
+// async-signal-safe calls are allowed here
+pub fn apply_in_child(actions: &[FdAction]) -> io::Result<()> {
+    for action in actions {
+        perform(*action)?;
+    }
+    Ok(())
+}
+
+// The descriptors of the interpreter that were replaced for a builtin command, with copies of their original values
+pub struct SavedFds {
+    saved: Vec<(RawFd, Option<RawFd>)>
+}
+
+impl SavedFds {
+    // Put the original descriptors back
+    pub fn restore(self) {
+        let _ = io::stdout().flush();
+        for (fd, copy) in self.saved.into_iter().rev() {
+            unsafe {
+                match copy {
+                    Some(copy) => {
+                        libc::dup2(copy, fd);
+                        libc::close(copy);
+                    },
+                    None => { libc::close(fd); }
+                }
+            }
+        }
+    }
+}
+
+// Apply the operations to the interpreter's own descriptors, e.g for a builtin command. The replaced descriptors
+// are saved first so that they can be restored afterwards
+pub fn apply_in_shell(actions: &[FdAction]) -> io::Result<SavedFds> {
+    let _ = io::stdout().flush();
+    let mut saved = SavedFds { saved: Vec::new() };
+    for action in actions {
+        let fd = match *action {
+            FdAction::Duplicate { fd, .. } | FdAction::Close(fd) => fd
+        };
+        if !saved.saved.iter().any(|(saved_fd, _)| *saved_fd == fd) {
+            let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, FIRST_PRIVATE_FD) };
+            saved.saved.push((fd, if copy == -1 { None } else { Some(copy) }));
+        }
+        if let Err(e) = perform(*action) {
+            saved.restore();
+            return Err(e);
+        }
+    }
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirection(fd: i32, kind: RedirectionKind, target: &str) -> Redirection {
+        Redirection { fd, kind, target: String::from(target), here_doc: None }
+    }
+
+    #[test]
+    fn duplications_and_closes() {
+        let mut shell = Shell::make(None);
+        let prepared = prepare(&mut shell, &[redirection(2, RedirectionKind::DuplicateOutput, "1"), redirection(0, RedirectionKind::DuplicateInput, "-")]).unwrap();
+        assert!(matches!(prepared.actions.as_slice(), [FdAction::Duplicate { source: 1, fd: 2 }, FdAction::Close(0)]));
+        let error = prepare(&mut shell, &[redirection(3, RedirectionKind::DuplicateOutput, "file")]).err().unwrap();
+        assert_eq!(error.to_string(), "file: ambiguous redirect");
+    }
+
+    #[test]
+    fn files_get_private_descriptors() {
+        let mut shell = Shell::make(None);
+        let path = std::env::temp_dir().join(format!("redirection-test-{}", std::process::id()));
+        let target = path.to_str().unwrap();
+        let prepared = prepare(&mut shell, &[redirection(1, RedirectionKind::DuplicateOutput, target)]).unwrap();
+        assert!(matches!(prepared.actions.as_slice(), [FdAction::Duplicate { source, fd: 1 }, FdAction::Duplicate { source: 1, fd: 2 }] if *source >= FIRST_PRIVATE_FD));
+        std::fs::remove_file(&path).unwrap();
+        let error = prepare(&mut shell, &[redirection(0, RedirectionKind::Input, target)]).err().unwrap();
+        assert_eq!(error.to_string(), format!("{}: No such file or directory", target));
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::commands::CommandList;
+use crate::jobs::JobTable;
+use crate::signals;
+use crate::terminal::Terminal;
+use crate::variables::{Variable, Variables};
+
+// The options set with the shopt builtin
+#[derive(Debug, Clone, Default)]
+pub struct ShellOptions {
+    // a pattern that matches no files expands to nothing instead of itself
+    pub nullglob: bool,
+    // a pattern that matches no files is an error
+    pub failglob: bool,
+    // the patterns match the names starting with a '.' too
+    pub dotglob: bool
+}
+
+impl ShellOptions {
+    pub const NAMES: [&'static str; 3] = ["dotglob", "failglob", "nullglob"];
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "nullglob" => Some(&mut self.nullglob),
+            "failglob" => Some(&mut self.failglob),
+            "dotglob" => Some(&mut self.dotglob),
+            _ => None
+        }
+    }
+}
+
+// A jump out of the commands being run, requested by the break, continue and return builtins. The commands left in the lists
+// are skipped until the loop or function it is meant for is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlFlow {
+    // leave this many loops
+    Break(usize),
+    // leave one loop less than this and go on with the next iteration of the loop around them
+    Continue(usize),
+    // leave the function
+    Return
+}
+
+// The state of a running function call
+pub struct FunctionFrame {
+    // the variables made local with the local builtin and what they were before, restored when the function returns
+    pub saved_variables: Vec<(String, Option<Variable>)>
+}
+
+// The state of the interpreter that the executed commands can change
+pub struct Shell {
+    // set by the exit builtin, the main loop stops after the current input
+    pub exit_requested: bool,
+    // set when the working directory changes so that the autocompletion can be updated
+    pub dir_changed: bool,
+    // the exit status of the last pipeline, $?
+    pub last_status: i32,
+    // the settings of the terminal the interpreter reads its input from. None in the copies of the shell running in the background
+    pub terminal: Option<Terminal>,
+    // the background and stopped jobs
+    pub jobs: JobTable,
+    pub variables: Variables,
+    // the exit status of the last command substitution in the command being expanded. A command with only assignments
+    // returns it as its own status
+    pub substitution_status: Option<i32>,
+    pub options: ShellOptions,
+    // the directories saved by pushd, the most recent first. The working directory is the top of the stack, it isn't stored here
+    pub dir_stack: Vec<String>,
+    // the number of loops the running command is in
+    pub loop_depth: usize,
+    pub control_flow: Option<ControlFlow>,
+    // set when Ctrl-C ended the foreground job, the rest of the input isn't run
+    pub interrupted: bool,
+    // the functions defined so far by name
+    pub functions: HashMap<String, Rc<CommandList>>,
+    // the running function calls, the innermost last
+    pub function_frames: Vec<FunctionFrame>,
+    // the number of files the source builtin is running
+    pub source_depth: usize,
+    // the positional parameters $1, $2... They are the arguments of the running function
+    pub positional: Vec<String>
+}
+
+impl Shell {
+    pub fn make(terminal: Option<Terminal>) -> Self {
+        let mut variables = Variables::from_environment();
+        // an inherited PWD is kept only if it names the working directory, it might hold the symbolic links of the path
+        if let Ok(current) = env::current_dir() {
+            let is_valid = variables.get("PWD").is_some_and(|pwd| Path::new(pwd).is_absolute() && fs::canonicalize(pwd).ok() == fs::canonicalize(&current).ok());
+            if !is_valid {
+                let _ = variables.set("PWD", &current.to_string_lossy());
+            }
+        }
+        Shell { exit_requested: false, dir_changed: false, last_status: 0, terminal, jobs: JobTable::make(), variables, substitution_status: None, options: ShellOptions::default(), dir_stack: Vec::new(),
+            loop_depth: 0, control_flow: None, interrupted: false, functions: HashMap::new(), function_frames: Vec::new(), source_depth: 0, positional: Vec::new() }
+    }
+
+    // Note a Ctrl-C that reached the interpreter while it ran builtins, e.g in a loop without programs, so that the rest
+    // of the command line is skipped. Only the interpreter with the terminal catches it, its copies are ended by it
+    pub fn poll_interrupt(&mut self) {
+        if self.terminal.is_some() && signals::take_interrupt() {
+            println!();
+            self.interrupted = true;
+        }
+    }
+
+    // Whether the commands left in the lists being run are skipped: the shell is exiting, a break, continue or return
+    // is on its way to its loop or function or Ctrl-C ended a command
+    pub fn skips_rest(&self) -> bool {
+        self.exit_requested || self.control_flow.is_some() || self.interrupted
+    }
+
+    // The working directory as the user reached it, i.e with the symbolic links the cd builtin followed
+    pub fn working_dir(&self) -> String {
+        match self.variables.get("PWD").filter(|pwd| Path::new(pwd).is_absolute()) {
+            Some(pwd) => String::from(pwd),
+            None => env::current_dir().map(|dir| dir.to_string_lossy().into_owned()).unwrap_or_default()
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The signals sent by the terminal for job control. The interpreter ignores them so that Ctrl-Z only stops the
+// foreground job and so that the interpreter can take the terminal back from a job without being stopped itself
+const JOB_CONTROL_SIGNALS: [libc::c_int; 3] = [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+// The signals sent by Ctrl-C and Ctrl-\. They are meant for the foreground job. The interpreter ignores them, except
+// that it notes a Ctrl-C while it runs a builtin itself, see catch_interrupt
+const INTERRUPT_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGQUIT];
+
+pub fn ignore_job_control_signals() {
+    for signal in JOB_CONTROL_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+}
+
+pub fn ignore_interrupt_signals() {
+    for signal in INTERRUPT_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+}
+
+// Set by the SIGINT handler of the interpreter, see catch_interrupt
+static INTERRUPT_PENDING: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn note_interrupt(_signal: libc::c_int) {
+    INTERRUPT_PENDING.store(true, Ordering::SeqCst);
+}
+
+// Catch Ctrl-C instead of ignoring it. The interpreter only gets it while it runs commands itself, e.g the wait builtin,
+// since the terminal sends it to the foreground job otherwise. The handler only notes it for take_interrupt and doesn't
+// restart the interrupted system calls, so a blocking waitpid returns
+pub fn catch_interrupt() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = note_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+// Whether Ctrl-C was pressed since the last call
+pub fn take_interrupt() -> bool {
+    INTERRUPT_PENDING.swap(false, Ordering::SeqCst)
+}
+
+// Let Ctrl-C and Ctrl-\ end the process again, e.g a copy of the interpreter running a command substitution
+pub fn default_interrupt_signals() {
+    for signal in INTERRUPT_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+// Restore the default handling of the signals the interpreter ignores. Called in the child processes before they start
+// the program, since ignored signals stay ignored over exec. Only async-signal-safe calls are allowed here
+pub fn reset_to_default() {
+    for signal in JOB_CONTROL_SIGNALS.iter().chain(INTERRUPT_SIGNALS.iter()).copied() {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
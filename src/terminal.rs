@@ -0,0 +1,77 @@
+use std::io;
+use std::os::fd::RawFd;
+use termios::*;
+
+pub static STDIN_FILENO: RawFd = 0;
+
+// The terminal settings of the interpreter. The line editor reads the input a character at a time without echo,
+// the executed programs get the settings the user had when the interpreter was started.
+// The terminal also decides which process group is in the foreground, i.e gets the input and the Ctrl-C / Ctrl-Z signals
+pub struct Terminal {
+    // a copy of the standard input file descriptor that is closed when a program is executed. Redirecting the
+    // standard input of a child process doesn't affect it, so the child can use it to take the terminal
+    fd: RawFd,
+    original: Termios,
+    shell_mode: Termios,
+    shell_pgid: libc::pid_t
+}
+
+impl Terminal {
+    // Read the current settings of the terminal. They are restored when the interpreter gives the terminal to a program or exits.
+    // The interpreter is put in its own process group which is made the foreground group of the terminal
+    pub fn make() -> io::Result<Terminal> {
+        let fd = unsafe { libc::fcntl(STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = Termios::from_fd(fd)?;
+        let mut shell_mode = original;
+        // disable canonical mode. Also disable echo for better control of displayed input.
+        // Ctrl-C and the other signal characters are read as input, the line editor handles them
+        shell_mode.c_lflag &= !(ICANON | ECHO | ISIG);
+
+        // fails harmlessly if the interpreter already leads its session
+        let shell_pgid = unsafe {
+            libc::setpgid(0, 0);
+            libc::getpgrp()
+        };
+        let terminal = Terminal { fd, original, shell_mode, shell_pgid };
+        terminal.give_to(shell_pgid)?;
+        Ok(terminal)
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    // Switch to the settings used by the line editor
+    pub fn enter_shell_mode(&self) -> io::Result<()> {
+        tcsetattr(self.fd, TCSANOW, &self.shell_mode)
+    }
+
+    // Switch back to the user's original settings, e.g before a program starts using the terminal
+    pub fn restore_original(&self) -> io::Result<()> {
+        tcsetattr(self.fd, TCSANOW, &self.original)
+    }
+
+    pub fn current_settings(&self) -> io::Result<Termios> {
+        Termios::from_fd(self.fd)
+    }
+
+    pub fn apply_settings(&self, settings: &Termios) -> io::Result<()> {
+        tcsetattr(self.fd, TCSADRAIN, settings)
+    }
+
+    // Make the process group the foreground group of the terminal
+    pub fn give_to(&self, pgid: libc::pid_t) -> io::Result<()> {
+        if unsafe { libc::tcsetpgrp(self.fd, pgid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // Make the interpreter the foreground group again
+    pub fn take_back(&self) -> io::Result<()> {
+        self.give_to(self.shell_pgid)
+    }
+}
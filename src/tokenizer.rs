@@ -0,0 +1,778 @@
+use std::fmt;
+
+// The location of a token in the input text. start and end are character offsets, line and column start from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize
+}
+
+// An error found while reading the input. If incomplete is set, the input ended in the middle of a construct
+// (an open quote, a trailing backslash...) and reading another line might complete it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub incomplete: bool
+}
+
+impl SyntaxError {
+    pub fn make(message: &str, line: usize, column: usize) -> Self {
+        SyntaxError { message: String::from(message), line, column, incomplete: false }
+    }
+
+    pub fn make_incomplete(message: &str, line: usize, column: usize) -> Self {
+        SyntaxError { message: String::from(message), line, column, incomplete: true }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "syntax error at line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Word,
+    Operator,
+    // the digits right before a redirection operator, e.g the 2 in '2>file'
+    IoNumber,
+    // an arithmetic command, '((expression))'. The text is the expression
+    Arithmetic,
+    // a conditional command, '[[ expression ]]'. The text is the expression, split into its words when the command runs
+    Conditional
+}
+
+// The operators that split words even without surrounding whitespace. Longer operators must come before their prefixes.
+// A newline is an operator too, it ends a command like ';'
+const OPERATORS: [&str; 21] = ["<<<", "<<-", "&>>", "<<", "&&", "&>", "||", ">>", ">&", ">|", "<>", "<&", ";;", "|", "&", ";", "<", ">", "(", ")", "\n"];
+
+// The words that have a meaning of their own when they come where a command name could: they start and end the compound
+// commands. Quoting any part of them makes them plain words
+pub const RESERVED_WORDS: [&str; 19] = ["!", "[[", "]]", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for", "function", "if", "in", "then", "until", "while"];
+
+// The reserved words after which a new command starts
+const COMMAND_PREFIX_WORDS: [&str; 9] = ["!", "{", "do", "elif", "else", "if", "then", "until", "while"];
+
+fn is_operator_start(c: char) -> bool {
+    OPERATORS.iter().any(|op| op.starts_with(c))
+}
+
+// A single token. The text is kept as it was written (quotes included) so that the later phases know which parts were quoted.
+// Line continuations (backslash + newline) are already removed from the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+    // the lines of the here-document if this is the delimiter word after '<<' or '<<-'
+    pub here_doc: Option<String>
+}
+
+// The delimiter of a here-document with the quotes removed, and whether any part of it was quoted
+pub fn here_doc_delimiter(text: &str) -> (String, bool) {
+    let mut delimiter = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => quoted = true,
+            '\\' => {
+                quoted = true;
+                if let Some(escaped) = chars.next() {
+                    delimiter.push(escaped);
+                }
+            },
+            _ => delimiter.push(c)
+        }
+    }
+    (delimiter, quoted)
+}
+
+// A here-document whose delimiter has been read but whose lines start only after the next newline
+struct PendingHereDoc {
+    token_index: usize,
+    strip_tabs: bool
+}
+
+impl Token {
+    pub fn is_operator(&self, op: &str) -> bool {
+        self.kind == TokenKind::Operator && self.text == op
+    }
+}
+
+// Where the text of a command substitution is within a case command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CasePart {
+    // the word before 'in'
+    Subject,
+    // the patterns of an item, a ')' ends them
+    Patterns,
+    // the commands of an item, ';;' ends them
+    Body
+}
+
+// Follows the case commands in the text of a command substitution while it is scanned for its closing parenthesis,
+// so that the ')' after a pattern isn't taken for it. Used by the tokenizer and by the expansion of '$(...)'
+pub struct CaseTracker {
+    cases: Vec<CasePart>,
+    word: String,
+    // some part of the word was quoted, it is no reserved word then
+    word_quoted: bool,
+    command_start: bool,
+    after_semicolon: bool
+}
+
+impl CaseTracker {
+    pub fn make() -> Self {
+        CaseTracker { cases: Vec::new(), word: String::new(), word_quoted: false, command_start: true, after_semicolon: false }
+    }
+
+    // Take a quoted or escaped character
+    pub fn take_quoted(&mut self) {
+        self.word.push('_');
+        self.word_quoted = true;
+        self.after_semicolon = false;
+    }
+
+    // Take an unquoted character. Returns whether it is a parenthesis around the patterns of a case item
+    pub fn take(&mut self, c: char) -> bool {
+        if !(c.is_whitespace() || "();&|<>".contains(c)) {
+            self.word.push(c);
+            self.after_semicolon = false;
+            return false;
+        }
+        let at_pattern_start = self.word.is_empty() && self.cases.last() == Some(&CasePart::Patterns);
+        self.end_word();
+        let mut is_pattern_parenthesis = false;
+        match c {
+            '(' => is_pattern_parenthesis = at_pattern_start,
+            ')' if self.cases.last() == Some(&CasePart::Patterns) => {
+                self.cases.pop();
+                self.cases.push(CasePart::Body);
+                is_pattern_parenthesis = true;
+            },
+            ';' if self.after_semicolon && self.cases.last() == Some(&CasePart::Body) => {
+                self.cases.pop();
+                self.cases.push(CasePart::Patterns);
+            },
+            _ => ()
+        }
+        if !c.is_whitespace() || c == '\n' {
+            self.command_start = !"<>".contains(c);
+        }
+        self.after_semicolon = c == ';' && !self.after_semicolon;
+        is_pattern_parenthesis
+    }
+
+    fn end_word(&mut self) {
+        if self.word.is_empty() {
+            return;
+        }
+        let reserved = if self.word_quoted { "" } else { self.word.as_str() };
+        match (self.cases.last(), reserved) {
+            (_, "case") if self.command_start => self.cases.push(CasePart::Subject),
+            (Some(CasePart::Subject), "in") => {
+                self.cases.pop();
+                self.cases.push(CasePart::Patterns);
+            },
+            (Some(CasePart::Patterns), "esac") => { self.cases.pop(); },
+            (Some(CasePart::Body), "esac") if self.command_start => { self.cases.pop(); },
+            _ => ()
+        }
+        self.command_start = COMMAND_PREFIX_WORDS.contains(&reserved);
+        self.word.clear();
+        self.word_quoted = false;
+    }
+}
+
+struct Tokenizer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    // set after '<<' or '<<-' (the value tells whether the tabs are stripped), the next word is a delimiter
+    expecting_delimiter: Option<bool>,
+    pending_here_docs: Vec<PendingHereDoc>
+}
+
+impl Tokenizer {
+    fn make(input: &str) -> Self {
+        Tokenizer { chars: input.chars().collect(), pos: 0, line: 1, column: 1, expecting_delimiter: None, pending_here_docs: Vec::new() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    // skip a backslash + newline pair if one is at the current position. Returns whether something was skipped
+    fn skip_line_continuation(&mut self) -> bool {
+        if self.peek() == Some('\\') && self.peek_at(1) == Some('\n') {
+            self.advance();
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    // skip the whitespace and the comment up to the next token. A newline is a token of its own, a '#' where a token
+    // would start begins a comment up to the end of the line
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some('\n') => break,
+                Some(c) if c.is_whitespace() => { self.advance(); },
+                Some('#') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.advance();
+                    }
+                },
+                Some('\\') if self.skip_line_continuation() => (),
+                _ => break
+            }
+        }
+    }
+
+    fn read_single_quoted(&mut self, text: &mut String) -> Result<(), SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        text.push(self.advance().unwrap());
+        loop {
+            match self.advance() {
+                Some('\'') => { text.push('\''); return Ok(()); },
+                Some(c) => text.push(c),
+                None => return Err(SyntaxError::make_incomplete("unterminated single quote", line, column))
+            }
+        }
+    }
+
+    fn read_double_quoted(&mut self, text: &mut String) -> Result<(), SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        text.push(self.advance().unwrap());
+        loop {
+            if self.skip_line_continuation() {
+                continue;
+            }
+            match self.advance() {
+                Some('"') => { text.push('"'); return Ok(()); },
+                Some('\\') => {
+                    text.push('\\');
+                    match self.advance() {
+                        Some(c) => text.push(c),
+                        None => return Err(SyntaxError::make_incomplete("unterminated double quote", line, column))
+                    }
+                },
+                Some('$') if self.peek() == Some('{') => {
+                    text.push('$');
+                    self.read_braced_parameter(text)?;
+                },
+                Some('$') if self.peek() == Some('(') => {
+                    text.push('$');
+                    self.read_command_substitution(text)?;
+                },
+                Some('`') => {
+                    text.push('`');
+                    self.read_backquoted(text)?;
+                },
+                Some(c) => text.push(c),
+                None => return Err(SyntaxError::make_incomplete("unterminated double quote", line, column))
+            }
+        }
+    }
+
+    // read a command substitution, '$(...)', starting at the '('. The commands inside can hold any tokens, the substitution
+    // ends at the parenthesis that closes the first one
+    fn read_command_substitution(&mut self, text: &mut String) -> Result<(), SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        let mut depth = 0;
+        let mut cases = CaseTracker::make();
+        loop {
+            if self.skip_line_continuation() {
+                continue;
+            }
+            match self.peek() {
+                // the parentheses around the patterns of a case command inside are no nesting
+                Some(c @ ('(' | ')')) if depth > 0 && cases.take(c) => {
+                    text.push(c);
+                    self.advance();
+                },
+                Some('(') => {
+                    depth += 1;
+                    text.push('(');
+                    self.advance();
+                },
+                Some(')') => {
+                    depth -= 1;
+                    text.push(')');
+                    self.advance();
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                },
+                Some('\'') => {
+                    cases.take_quoted();
+                    self.read_single_quoted(text)?;
+                },
+                Some('"') => {
+                    cases.take_quoted();
+                    self.read_double_quoted(text)?;
+                },
+                Some('`') => {
+                    cases.take_quoted();
+                    text.push('`');
+                    self.advance();
+                    self.read_backquoted(text)?;
+                },
+                Some('$') if self.peek_at(1) == Some('{') => {
+                    cases.take_quoted();
+                    text.push('$');
+                    self.advance();
+                    self.read_braced_parameter(text)?;
+                },
+                Some('\\') => {
+                    cases.take_quoted();
+                    text.push('\\');
+                    self.advance();
+                    if let Some(escaped) = self.advance() {
+                        text.push(escaped);
+                    }
+                },
+                Some(c) => {
+                    cases.take(c);
+                    text.push(c);
+                    self.advance();
+                },
+                None => return Err(SyntaxError::make_incomplete("unterminated command substitution", line, column))
+            }
+        }
+    }
+
+    // read an old style command substitution, '`...`', after the opening backquote. A backslash escapes the next character
+    fn read_backquoted(&mut self, text: &mut String) -> Result<(), SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        loop {
+            if self.skip_line_continuation() {
+                continue;
+            }
+            match self.advance() {
+                Some('`') => {
+                    text.push('`');
+                    return Ok(());
+                },
+                Some('\\') => {
+                    text.push('\\');
+                    if let Some(escaped) = self.advance() {
+                        text.push(escaped);
+                    }
+                },
+                Some(c) => text.push(c),
+                None => return Err(SyntaxError::make_incomplete("unterminated command substitution", line, column))
+            }
+        }
+    }
+
+    // read a parameter expansion in braces, '${...}', starting at the '{'. Whitespace and operators inside the braces
+    // don't end the word
+    fn read_braced_parameter(&mut self, text: &mut String) -> Result<(), SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        text.push(self.advance().unwrap());
+        loop {
+            if self.skip_line_continuation() {
+                continue;
+            }
+            match self.peek() {
+                Some('}') => {
+                    text.push('}');
+                    self.advance();
+                    return Ok(());
+                },
+                Some('\'') => self.read_single_quoted(text)?,
+                Some('"') => self.read_double_quoted(text)?,
+                Some('$') if self.peek_at(1) == Some('{') => {
+                    text.push('$');
+                    self.advance();
+                    self.read_braced_parameter(text)?;
+                },
+                Some('$') if self.peek_at(1) == Some('(') => {
+                    text.push('$');
+                    self.advance();
+                    self.read_command_substitution(text)?;
+                },
+                Some('`') => {
+                    text.push('`');
+                    self.advance();
+                    self.read_backquoted(text)?;
+                },
+                Some('\\') => {
+                    text.push('\\');
+                    self.advance();
+                    match self.advance() {
+                        Some(escaped) => text.push(escaped),
+                        None => return Err(SyntaxError::make_incomplete("unterminated parameter expansion", line, column))
+                    }
+                },
+                Some(c) => {
+                    text.push(c);
+                    self.advance();
+                },
+                None => return Err(SyntaxError::make_incomplete("unterminated parameter expansion", line, column))
+            }
+        }
+    }
+
+    // read an arithmetic command, '((...))', starting at the first '('. The expression ends at the parentheses that
+    // close both. If the second '(' is closed earlier, e.g '((cd dir) && make)', these are nested subshells: nothing
+    // is read and None is returned
+    fn read_arithmetic_command(&mut self) -> Result<Option<Token>, SyntaxError> {
+        let (start, line, column) = (self.pos, self.line, self.column);
+        let mut text = String::new();
+        self.read_command_substitution(&mut text).map_err(|e| SyntaxError { message: String::from("unterminated arithmetic command"), ..e })?;
+        if !closes_together(&text) {
+            (self.pos, self.line, self.column) = (start, line, column);
+            return Ok(None);
+        }
+        let expression = &text[2..text.len() - 2];
+        Ok(Some(Token { kind: TokenKind::Arithmetic, text: String::from(expression), span: Span { start, end: self.pos, line, column }, here_doc: None }))
+    }
+
+    // whether '[[' starts at the current position as a word of its own
+    fn at_conditional_start(&self) -> bool {
+        self.peek() == Some('[') && self.peek_at(1) == Some('[') && self.peek_at(2).is_none_or(|c| c.is_whitespace())
+    }
+
+    // whether ']]' is at the current position as a word of its own
+    fn at_conditional_end(&self) -> bool {
+        self.peek() == Some(']') && self.peek_at(1) == Some(']')
+            && self.peek_at(2).is_none_or(|c| c.is_whitespace() || matches!(c, ';' | '&' | '|' | ')'))
+    }
+
+    // read a conditional command, '[[ ... ]]', starting at the '[['. The operators inside are part of the expression,
+    // the command ends at the first unquoted ']]' word
+    fn read_conditional_command(&mut self) -> Result<Token, SyntaxError> {
+        let (start, line, column) = (self.pos, self.line, self.column);
+        self.advance();
+        self.advance();
+        let mut text = String::new();
+        let mut word_start = true;
+        loop {
+            if self.skip_line_continuation() {
+                continue;
+            }
+            if word_start && self.at_conditional_end() {
+                self.advance();
+                self.advance();
+                break;
+            }
+            let Some(c) = self.peek() else {
+                return Err(SyntaxError::make_incomplete("unterminated conditional expression", line, column));
+            };
+            word_start = c.is_whitespace();
+            match c {
+                '\'' => self.read_single_quoted(&mut text)?,
+                '"' => self.read_double_quoted(&mut text)?,
+                '$' if matches!(self.peek_at(1), Some('{') | Some('(')) => {
+                    text.push('$');
+                    self.advance();
+                    if self.peek() == Some('{') {
+                        self.read_braced_parameter(&mut text)?;
+                    } else {
+                        self.read_command_substitution(&mut text)?;
+                    }
+                },
+                '`' => {
+                    text.push('`');
+                    self.advance();
+                    self.read_backquoted(&mut text)?;
+                },
+                '\\' => {
+                    text.push('\\');
+                    self.advance();
+                    if let Some(escaped) = self.advance() {
+                        text.push(escaped);
+                    }
+                },
+                _ => {
+                    text.push(c);
+                    self.advance();
+                }
+            }
+        }
+        Ok(Token { kind: TokenKind::Conditional, text: String::from(text.trim()), span: Span { start, end: self.pos, line, column }, here_doc: None })
+    }
+
+    fn read_word(&mut self) -> Result<Token, SyntaxError> {
+        let (start, line, column) = (self.pos, self.line, self.column);
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                _ if c.is_whitespace() || is_operator_start(c) => break,
+                '\'' => self.read_single_quoted(&mut text)?,
+                '"' => self.read_double_quoted(&mut text)?,
+                '$' if self.peek_at(1) == Some('{') => {
+                    text.push('$');
+                    self.advance();
+                    self.read_braced_parameter(&mut text)?;
+                },
+                '$' if self.peek_at(1) == Some('(') => {
+                    text.push('$');
+                    self.advance();
+                    self.read_command_substitution(&mut text)?;
+                },
+                '`' => {
+                    text.push('`');
+                    self.advance();
+                    self.read_backquoted(&mut text)?;
+                },
+                '\\' => {
+                    if self.skip_line_continuation() {
+                        continue;
+                    }
+                    self.advance();
+                    match self.advance() {
+                        Some(escaped) => { text.push('\\'); text.push(escaped); },
+                        None => return Err(SyntaxError::make_incomplete("unexpected end of input after backslash", self.line, self.column))
+                    }
+                },
+                _ => { text.push(c); self.advance(); }
+            }
+        }
+        let is_io_number = !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) && matches!(self.peek(), Some('<') | Some('>'));
+        let kind = if is_io_number { TokenKind::IoNumber } else { TokenKind::Word };
+        Ok(Token { kind, text, span: Span { start, end: self.pos, line, column }, here_doc: None })
+    }
+
+    // read the longest operator that starts at the current position
+    fn read_operator(&mut self) -> Option<Token> {
+        let (start, line, column) = (self.pos, self.line, self.column);
+        let op = OPERATORS.iter().find(|op| {
+            op.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+        })?;
+        for _ in op.chars() {
+            self.advance();
+        }
+        Some(Token { kind: TokenKind::Operator, text: String::from(*op), span: Span { start, end: self.pos, line, column }, here_doc: None })
+    }
+
+    // read the lines up to the delimiter line. With strip_tabs the leading tabs of every line are removed
+    fn read_here_doc_body(&mut self, delimiter: &str, strip_tabs: bool) -> Result<String, SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        let mut body = String::new();
+        loop {
+            if self.peek().is_none() {
+                return Err(SyntaxError::make_incomplete(&format!("here-document not terminated with '{}'", delimiter), line, column));
+            }
+            let mut text = String::new();
+            while let Some(c) = self.advance() {
+                if c == '\n' {
+                    break;
+                }
+                text.push(c);
+            }
+            let text = if strip_tabs { text.trim_start_matches('\t') } else { text.as_str() };
+            if text == delimiter {
+                return Ok(body);
+            }
+            body.push_str(text);
+            body.push('\n');
+        }
+    }
+
+    // read the bodies of the here-documents started on the line that just ended
+    fn read_pending_here_docs(&mut self, tokens: &mut [Token]) -> Result<(), SyntaxError> {
+        for pending in std::mem::take(&mut self.pending_here_docs) {
+            let (delimiter, _) = here_doc_delimiter(&tokens[pending.token_index].text);
+            tokens[pending.token_index].here_doc = Some(self.read_here_doc_body(&delimiter, pending.strip_tabs)?);
+        }
+        Ok(())
+    }
+}
+
+// Whether the second '(' of '((...))' is closed by the next to last ')', i.e the text is an arithmetic command and not
+// a subshell that starts with another one. Quoted parentheses don't count
+fn closes_together(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    let mut depth = 0;
+    let mut i = 1;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 2 == chars.len();
+                }
+            },
+            '\\' => i += 1,
+            quote @ ('\'' | '"') => {
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    i += if chars[i] == '\\' && quote == '"' { 2 } else { 1 };
+                }
+            },
+            _ => ()
+        }
+        i += 1;
+    }
+    false
+}
+
+// Whether the next token would start a command, i.e it comes first, after an operator that isn't a redirection
+// or after a reserved word like 'if' or 'do'
+fn at_command_start(tokens: &[Token]) -> bool {
+    tokens.last().is_none_or(|token| match token.kind {
+        TokenKind::Operator => ["|", "&&", "||", ";", ";;", "&", "(", ")", "\n"].contains(&token.text.as_str()),
+        TokenKind::Word => COMMAND_PREFIX_WORDS.contains(&token.text.as_str()),
+        _ => false
+    })
+}
+
+// Split the input into tokens
+pub fn tokenize(input: &str) -> Result<Vec<Token>, SyntaxError> {
+    let mut tokenizer = Tokenizer::make(input);
+    let mut tokens = Vec::new();
+    loop {
+        tokenizer.skip_whitespace();
+        match tokenizer.peek() {
+            None => break,
+            Some('\n') => {
+                let newline = tokenizer.read_operator().unwrap();
+                tokens.push(newline);
+                tokenizer.read_pending_here_docs(&mut tokens)?;
+                continue;
+            },
+            _ => ()
+        }
+        let arithmetic = if tokenizer.peek() == Some('(') && tokenizer.peek_at(1) == Some('(') {
+            tokenizer.read_arithmetic_command()?
+        } else {
+            None
+        };
+        if let Some(arithmetic) = arithmetic {
+            tokens.push(arithmetic);
+        } else if tokenizer.at_conditional_start() && at_command_start(&tokens) {
+            tokens.push(tokenizer.read_conditional_command()?);
+        } else if let Some(op) = tokenizer.read_operator() {
+            tokenizer.expecting_delimiter = match op.text.as_str() {
+                "<<" => Some(false),
+                "<<-" => Some(true),
+                _ => None
+            };
+            tokens.push(op);
+        } else {
+            let word = tokenizer.read_word()?;
+            if let Some(strip_tabs) = tokenizer.expecting_delimiter.take() {
+                tokenizer.pending_here_docs.push(PendingHereDoc { token_index: tokens.len(), strip_tabs });
+            }
+            tokens.push(word);
+        }
+    }
+    // the lines of the here-documents haven't been given yet
+    if let Some(pending) = tokenizer.pending_here_docs.first() {
+        let span = tokens[pending.token_index].span;
+        return Err(SyntaxError::make_incomplete("here-document without its lines", span.line, span.column));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(input: &str) -> Vec<String> {
+        tokenize(input).unwrap().into_iter().map(|token| token.text).collect()
+    }
+
+    fn is_incomplete(input: &str) -> bool {
+        tokenize(input).is_err_and(|e| e.incomplete)
+    }
+
+    #[test]
+    fn quotes_and_escapes_stay_in_the_word() {
+        assert_eq!(texts(r#"echo 'a b' "c $d" e\ f"#), ["echo", "'a b'", "\"c $d\"", "e\\ f"]);
+        assert_eq!(texts("echo $(a | b) ${x:-a b} `c d` \"$(e \")\")\""), ["echo", "$(a | b)", "${x:-a b}", "`c d`", "\"$(e \")\")\""]);
+        assert_eq!(texts("x=$(case a in (a) (b);; 'esac') c;; esac) y"), ["x=$(case a in (a) (b);; 'esac') c;; esac)", "y"]);
+        assert_eq!(texts("$(echo case in a) b"), ["$(echo case in a)", "b"]);
+    }
+
+    #[test]
+    fn operators_split_words() {
+        assert_eq!(texts("ls|wc -l>out&"), ["ls", "|", "wc", "-l", ">", "out", "&"]);
+        assert_eq!(texts("echo 'a|b' c\\&"), ["echo", "'a|b'", "c\\&"]);
+        assert!(tokenize("a<b").unwrap()[1].is_operator("<"));
+        assert_eq!(texts("a&&b||c;d&"), ["a", "&&", "b", "||", "c", ";", "d", "&"]);
+        assert_eq!(texts("cat<>f>>g 2>&1 &>h"), ["cat", "<>", "f", ">>", "g", "2", ">&", "1", "&>", "h"]);
+        let tokens = tokenize("cmd 2>err x2>y").unwrap();
+        assert_eq!(tokens[1].kind, TokenKind::IoNumber);
+        assert_eq!(tokens[4].kind, TokenKind::Word);
+    }
+
+    #[test]
+    fn comments_run_to_the_end_of_the_line() {
+        assert_eq!(texts("echo a # b 'c\nd#e f#"), ["echo", "a", "\n", "d#e", "f#"]);
+        assert_eq!(texts("a;#b\n#c"), ["a", ";", "\n"]);
+        assert_eq!(texts("echo '#' \\# ${#x}"), ["echo", "'#'", "\\#", "${#x}"]);
+        let tokens = tokenize("cat <<E # c\nx\nE").unwrap();
+        assert_eq!(tokens[2].here_doc.as_deref(), Some("x\n"));
+    }
+
+    #[test]
+    fn continuations_are_removed() {
+        assert_eq!(texts("ec\\\nho a"), ["echo", "a"]);
+    }
+
+    #[test]
+    fn spans_count_lines_and_columns() {
+        let tokens = tokenize("a \\\n  bc").unwrap();
+        assert_eq!((tokens[1].span.line, tokens[1].span.column), (2, 3));
+    }
+
+    #[test]
+    fn unfinished_input_is_incomplete() {
+        assert!(is_incomplete("echo 'abc"));
+        assert!(is_incomplete("echo \"abc"));
+        assert!(is_incomplete("echo abc\\"));
+        assert!(is_incomplete("echo $(date"));
+        assert!(is_incomplete("echo `date"));
+        assert!(is_incomplete("cat <<EOF"));
+        assert!(is_incomplete("cat <<EOF\nline\n"));
+        assert!(!is_incomplete("echo 'abc'"));
+    }
+
+    #[test]
+    fn here_documents_take_the_following_lines() {
+        let tokens = tokenize("cat <<EOF; echo\nhi $x\nEOF\nnext").unwrap();
+        assert_eq!(tokens[2].here_doc.as_deref(), Some("hi $x\n"));
+        assert_eq!(tokens.last().unwrap().text, "next");
+        let tokens = tokenize("cat <<-'E'\n\tx\n\tE\n").unwrap();
+        assert_eq!(tokens[2].here_doc.as_deref(), Some("x\n"));
+        assert_eq!(here_doc_delimiter("'E'"), (String::from("E"), true));
+    }
+
+    #[test]
+    fn double_parentheses() {
+        let tokens = tokenize("((i = 1 + 2)) && echo $((i))").unwrap();
+        assert_eq!((tokens[0].kind, tokens[0].text.as_str()), (TokenKind::Arithmetic, "i = 1 + 2"));
+        assert_eq!(tokens[3].text, "$((i))");
+        assert!(is_incomplete("((i = 1"));
+        assert_eq!(texts("((cd a) && b)"), ["(", "(", "cd", "a", ")", "&&", "b", ")"]);
+    }
+}
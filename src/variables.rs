@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    // None for a name export or readonly marked before it got a value
+    pub value: Option<String>,
+    // the elements of an array variable, e.g BASH_REMATCH. The value is the first element
+    pub elements: Option<Vec<String>>,
+    // exported variables are given to the executed programs as their environment
+    pub exported: bool,
+    pub readonly: bool
+}
+
+// The shell variables. The variables of the environment the interpreter was started with are exported
+pub struct Variables {
+    variables: HashMap<String, Variable>
+}
+
+// A valid variable name starts with a letter or an underscore and continues with letters, digits and underscores
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false
+    }
+}
+
+// Split an assignment word, NAME=value, into the name and the value. None if the word is not an assignment
+pub fn split_assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    is_valid_name(name).then_some((name, value))
+}
+
+impl Variables {
+    pub fn from_environment() -> Self {
+        let variables = std::env::vars()
+            .map(|(name, value)| (name, Variable { value: Some(value), elements: None, exported: true, readonly: false }))
+            .collect();
+        Variables { variables }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).and_then(|v| v.value.as_deref())
+    }
+
+    // Set the value of a variable. A new variable is not exported
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match self.variables.get_mut(name) {
+            Some(variable) if variable.readonly => Err(format!("{}: readonly variable", name)),
+            Some(variable) => {
+                variable.value = Some(String::from(value));
+                // for an array this sets the first element
+                if let Some(first) = variable.elements.as_mut().and_then(|elements| elements.first_mut()) {
+                    *first = String::from(value);
+                }
+                Ok(())
+            },
+            None => {
+                self.variables.insert(String::from(name), Variable { value: Some(String::from(value)), elements: None, exported: false, readonly: false });
+                Ok(())
+            }
+        }
+    }
+
+    // The elements of the variable, a variable that is not an array has its value as the only element
+    pub fn elements(&self, name: &str) -> Option<Vec<String>> {
+        let variable = self.variables.get(name)?;
+        variable.elements.clone().or_else(|| Some(vec![variable.value.clone()?]))
+    }
+
+    // Make the variable an array with the elements
+    pub fn set_array(&mut self, name: &str, elements: Vec<String>) -> Result<(), String> {
+        self.set(name, elements.first().map_or("", |first| first.as_str()))?;
+        if let Some(variable) = self.variables.get_mut(name) {
+            variable.elements = Some(elements);
+        }
+        Ok(())
+    }
+
+    // A copy of the variable, to be put back with restore
+    pub fn save(&self, name: &str) -> Option<Variable> {
+        self.variables.get(name).cloned()
+    }
+
+    // Put back a variable saved with save, or remove it if it didn't exist then
+    pub fn restore(&mut self, name: &str, saved: Option<Variable>) {
+        match saved {
+            Some(variable) => { self.variables.insert(String::from(name), variable); },
+            None => { self.variables.remove(name); }
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), String> {
+        match self.variables.get(name) {
+            Some(variable) if variable.readonly => Err(format!("{}: cannot unset: readonly variable", name)),
+            _ => {
+                self.variables.remove(name);
+                Ok(())
+            }
+        }
+    }
+
+    // Mark the variable exported. A name without a variable is only marked, it gets into the environment once it is set
+    pub fn export(&mut self, name: &str) {
+        self.variables.entry(String::from(name))
+            .or_insert(Variable { value: None, elements: None, exported: false, readonly: false })
+            .exported = true;
+    }
+
+    // Mark the variable readonly. A name without a variable is only marked and can't be set anymore
+    pub fn set_readonly(&mut self, name: &str) {
+        self.variables.entry(String::from(name))
+            .or_insert(Variable { value: None, elements: None, exported: false, readonly: false })
+            .readonly = true;
+    }
+
+    // The variables sorted by name
+    pub fn sorted(&self) -> Vec<(&String, &Variable)> {
+        let mut variables: Vec<(&String, &Variable)> = self.variables.iter().collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    // The environment given to the executed programs
+    pub fn environment(&self) -> Vec<(String, String)> {
+        self.variables.iter()
+            .filter(|(_, v)| v.exported)
+            .filter_map(|(name, v)| Some((name.clone(), v.value.clone()?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> Variables {
+        Variables { variables: HashMap::new() }
+    }
+
+    #[test]
+    fn names_and_assignments() {
+        assert!(is_valid_name("_a1") && is_valid_name("A"));
+        assert!(!is_valid_name("1a") && !is_valid_name("a-b") && !is_valid_name(""));
+        assert_eq!(split_assignment("A=b=c"), Some(("A", "b=c")));
+        assert_eq!(split_assignment("1A=b"), None);
+        assert_eq!(split_assignment("ab"), None);
+    }
+
+    #[test]
+    fn readonly_variables() {
+        let mut variables = empty();
+        variables.set("A", "1").unwrap();
+        variables.set_readonly("A");
+        assert_eq!(variables.set("A", "2"), Err(String::from("A: readonly variable")));
+        assert_eq!(variables.unset("A"), Err(String::from("A: cannot unset: readonly variable")));
+        assert_eq!(variables.get("A"), Some("1"));
+    }
+
+    #[test]
+    fn only_exported_variables_are_in_the_environment() {
+        let mut variables = empty();
+        variables.set("A", "1").unwrap();
+        variables.set("B", "2").unwrap();
+        variables.export("B");
+        assert_eq!(variables.environment(), [(String::from("B"), String::from("2"))]);
+        variables.unset("B").unwrap();
+        assert!(variables.environment().is_empty());
+    }
+
+    #[test]
+    fn saved_variables_are_restored() {
+        let mut variables = empty();
+        variables.set("A", "1").unwrap();
+        variables.export("A");
+        let (saved_a, saved_b) = (variables.save("A"), variables.save("B"));
+        variables.set("A", "2").unwrap();
+        variables.set("B", "3").unwrap();
+        variables.restore("A", saved_a);
+        variables.restore("B", saved_b);
+        assert_eq!(variables.get("A"), Some("1"));
+        assert_eq!(variables.get("B"), None);
+        assert_eq!(variables.environment(), [(String::from("A"), String::from("1"))]);
+    }
+
+    #[test]
+    fn marking_an_unset_name_sets_no_value() {
+        let mut variables = empty();
+        variables.export("A");
+        variables.set_readonly("B");
+        assert_eq!((variables.get("A"), variables.elements("A")), (None, None));
+        assert!(variables.environment().is_empty());
+        variables.set("A", "1").unwrap();
+        assert_eq!(variables.environment(), [(String::from("A"), String::from("1"))]);
+        assert_eq!(variables.set("B", "2"), Err(String::from("B: readonly variable")));
+        assert_eq!(variables.get("B"), None);
+    }
+}